@@ -0,0 +1,163 @@
+use crate::chacha::ChaCha20;
+use crate::error::Error;
+use crate::kdf::SharedSecret;
+use crate::poly1305::{self, Poly1305};
+
+/// The size of an authentication tag in bytes
+pub const TAG_SIZE: usize = 16;
+
+/// The ChaCha20-Poly1305 authenticated encryption construction (RFC 8439).
+///
+/// A nonce must never be reused with the same key.
+///
+///```
+///# use partav2::aead::*;
+/// let aead = ChaCha20Poly1305::new([7; 32]);
+///
+/// let sealed = aead.seal(&[1; 12], b"header", b"attack at dawn");
+/// assert_eq!(aead.open(&[1; 12], b"header", &sealed).unwrap(), b"attack at dawn");
+///
+/// assert!(aead.open(&[1; 12], b"other header", &sealed).is_err());
+///```
+#[derive(Clone)]
+pub struct ChaCha20Poly1305 {
+    key: [u8; 32]
+}
+
+impl ChaCha20Poly1305 {
+    /// A new AEAD from a 32 byte key.
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            key
+        }
+    }
+
+    /// A new AEAD keyed by a shared secret from the key exchange.
+    ///
+    /// Fails if the secret is not 32 bytes long.
+    ///
+    ///```
+    ///# use partav2::aead::*;
+    /// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+    /// let (key2, public2) = partav2::respond(public1).unwrap();
+    /// let key1 = partav2::finalise(private1, public2).unwrap();
+    ///
+    /// let sealed = ChaCha20Poly1305::from_secret(&key2).unwrap().seal(&[0; 12], &[], b"hello");
+    /// assert_eq!(ChaCha20Poly1305::from_secret(&key1).unwrap().open(&[0; 12], &[], &sealed).unwrap(), b"hello");
+    ///```
+    pub fn from_secret(secret: &SharedSecret) -> Result<Self, Error> {
+        Ok(Self::new(secret.as_bytes().try_into().map_err(|_| Error::InvalidLength)?))
+    }
+
+    /// The tag over the associated data and ciphertext, with the one-time key from block 0.
+    fn tag(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_SIZE] {
+        let keystream = ChaCha20::from_bytes(self.key, 0, *nonce).keystream();
+        let mut mac = Poly1305::new(keystream[0..32].try_into().unwrap());
+
+        mac.absorb(aad);
+        mac.absorb(&[0; 16][..(16 - aad.len() % 16) % 16]);
+        mac.absorb(ciphertext);
+        mac.absorb(&[0; 16][..(16 - ciphertext.len() % 16) % 16]);
+        mac.absorb(&(aad.len() as u64).to_le_bytes());
+        mac.absorb(&(ciphertext.len() as u64).to_le_bytes());
+
+        mac.finalise()
+    }
+
+    /// Encrypts and authenticates the plaintext, and authenticates the associated data.
+    ///
+    /// seal(nonce, aad, plaintext) -> ciphertext || tag
+    pub fn seal(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut out = plaintext.to_vec();
+        ChaCha20::from_bytes(self.key, 1, *nonce).apply_keystream(&mut out);
+
+        let tag = self.tag(nonce, aad, &out);
+        out.extend(tag);
+
+        out
+    }
+
+    /// Verifies and decrypts a sealed message.
+    ///
+    /// open(nonce, aad, ciphertext || tag) -> plaintext
+    ///
+    /// Fails if the message is shorter than a tag, or the tag does not match.
+    pub fn open(&self, nonce: &[u8; 12], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < TAG_SIZE {
+            return Err(Error::Truncated);
+        }
+
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_SIZE);
+
+        if !poly1305::verify(&self.tag(nonce, aad, ciphertext), tag.try_into().unwrap()) {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        let mut out = ciphertext.to_vec();
+        ChaCha20::from_bytes(self.key, 1, *nonce).apply_keystream(&mut out);
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn rfc8439_aead() {
+        // RFC 8439 section 2.8.2
+        let key: [u8; 32] = core::array::from_fn(|i| 0x80 + i as u8);
+        let nonce = [0x07, 0, 0, 0, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let expected = hex(&[
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6",
+            "3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36",
+            "92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc",
+            "3ff4def08e4b7a9de576d26586cec64b6116",
+            "1ae10b594f09e26a7e902ecbd0600691"
+        ].concat());
+
+        let aead = ChaCha20Poly1305::new(key);
+        let sealed = aead.seal(&nonce, &aad, plaintext);
+
+        assert_eq!(sealed, expected);
+        assert_eq!(aead.open(&nonce, &aad, &sealed).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn tampering() {
+        let aead = ChaCha20Poly1305::new([3; 32]);
+        let sealed = aead.seal(&[5; 12], b"aad", b"a message longer than a single poly1305 block");
+
+        for i in 0..sealed.len() {
+            let mut bad = sealed.clone();
+            bad[i] ^= 0x80;
+
+            assert_eq!(aead.open(&[5; 12], b"aad", &bad), Err(Error::AuthenticationFailed));
+        }
+
+        assert_eq!(aead.open(&[6; 12], b"aad", &sealed), Err(Error::AuthenticationFailed));
+        assert_eq!(ChaCha20Poly1305::new([4; 32]).open(&[5; 12], b"aad", &sealed), Err(Error::AuthenticationFailed));
+        assert_eq!(aead.open(&[5; 12], b"aad", &sealed[..15]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn empty_messages() {
+        let aead = ChaCha20Poly1305::new([9; 32]);
+
+        for len in [0, 1, 15, 16, 17, 64, 65] {
+            let plaintext = vec![0x42; len];
+            let sealed = aead.seal(&[0; 12], &plaintext, &plaintext);
+
+            assert_eq!(sealed.len(), len + TAG_SIZE);
+            assert_eq!(aead.open(&[0; 12], &plaintext, &sealed).unwrap(), plaintext);
+        }
+    }
+}
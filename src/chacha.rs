@@ -1,7 +1,10 @@
 /// A ChaCha20 state
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ChaCha20 {
-    pub state: [u32; 16]
+    pub state: [u32; 16],
+
+    /// Whether the keystream of the last block has been used, so the counter has wrapped to 0
+    spent: bool
 }
 
 impl ChaCha20 {
@@ -13,26 +16,83 @@ impl ChaCha20 {
                 key[0], key[1], key[2], key[3],
                 key[4], key[5], key[6], key[7],
                 block, nonce[0], nonce[1], nonce[2]
-            ]
+            ],
+            spent: false
         }
     }
 
+    /// A new chacha20 state from a byte key and nonce, as laid out in RFC 8439.
+    ///
+    ///```
+    ///# use partav2::chacha::*;
+    /// assert_eq!(
+    ///     ChaCha20::from_bytes(
+    ///         core::array::from_fn(|i| i as u8),
+    ///         1,
+    ///         [0, 0, 0, 0x09, 0, 0, 0, 0x4a, 0, 0, 0, 0]
+    ///     ),
+    ///     ChaCha20::new(
+    ///         [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c],
+    ///         1,
+    ///         [0x09000000, 0x4a000000, 0x00000000]
+    ///     )
+    /// );
+    ///```
+    pub fn from_bytes(key: [u8; 32], block: u32, nonce: [u8; 12]) -> Self {
+        let mut words = [0u32; 8];
+        for (word, bytes) in words.iter_mut().zip(key.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+
+        let mut nonce_words = [0u32; 3];
+        for (word, bytes) in nonce_words.iter_mut().zip(nonce.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+
+        Self::new(words, block, nonce_words)
+    }
+
+    /// The block counter of the state.
+    pub fn counter(&self) -> u32 {
+        self.state[12]
+    }
+
+    /// Seeks to the start of a block, so the next keystream begins there.
+    ///
+    /// This is the only way to use a state again once its keystream is exhausted.
+    ///
+    ///```
+    ///# use partav2::chacha::*;
+    /// let mut chacha = ChaCha20::from_bytes([0; 32], 0, [0; 12]);
+    /// let mut whole = [0u8; 128];
+    /// chacha.apply_keystream(&mut whole);
+    ///
+    /// chacha.seek(1);
+    /// let mut second = [0u8; 64];
+    /// chacha.apply_keystream(&mut second);
+    ///
+    /// assert_eq!(second, whole[64..]);
+    ///```
+    pub fn seek(&mut self, block: u32) {
+        self.state[12] = block;
+        self.spent = false;
+    }
+
     /// ChaCha20 quarter round on a state
     ///
     ///```
     ///# use partav2::chacha::*;
-    /// assert_eq!(ChaCha20 {
-    ///         state: [
-    ///             0x11111111, 0x01020304, 0x9b8d6f43, 0x01234567,
-    ///             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
-    ///         ]
-    ///     }.quarter_round(0, 1, 2, 3),
-    ///     ChaCha20 {
-    ///         state: [
-    ///             0xea2a92f4, 0xcb1cf8ce, 0x4581472e, 0x5881c4bb,
-    ///             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
-    ///         ]
-    ///     }
+    /// let mut chacha = ChaCha20::new([0; 8], 0, [0; 3]);
+    /// chacha.state = [
+    ///     0x11111111, 0x01020304, 0x9b8d6f43, 0x01234567,
+    ///     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+    /// ];
+    /// assert_eq!(
+    ///     chacha.quarter_round(0, 1, 2, 3).state,
+    ///     [
+    ///         0xea2a92f4, 0xcb1cf8ce, 0x4581472e, 0x5881c4bb,
+    ///         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+    ///     ]
     /// );
     ///```
     pub fn quarter_round(self, a: usize, b: usize, c: usize, d: usize) -> Self {
@@ -40,19 +100,19 @@ impl ChaCha20 {
 
         out.state[a] = out.state[a].wrapping_add(out.state[b]);
         out.state[d] ^= out.state[a];
-        out.state[d] = (out.state[d] << 16) | (out.state[d] >> (32 - 16));
+        out.state[d] = out.state[d].rotate_left(16);
 
         out.state[c] = out.state[c].wrapping_add(out.state[d]);
         out.state[b] ^= out.state[c];
-        out.state[b] = (out.state[b] << 12) | (out.state[b] >> (32 - 12));
+        out.state[b] = out.state[b].rotate_left(12);
 
         out.state[a] = out.state[a].wrapping_add(out.state[b]);
         out.state[d] ^= out.state[a];
-        out.state[d] = (out.state[d] << 8) | (out.state[d] >> (32 - 8));
+        out.state[d] = out.state[d].rotate_left(8);
 
         out.state[c] = out.state[c].wrapping_add(out.state[d]);
         out.state[b] ^= out.state[c];
-        out.state[b] = (out.state[b] << 7) | (out.state[b] >> (32 - 7));
+        out.state[b] = out.state[b].rotate_left(7);
 
         out
     }
@@ -65,15 +125,13 @@ impl ChaCha20 {
     ///         [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c],
     ///         1,
     ///         [0x09000000, 0x4a000000, 0x00000000]
-    ///     ).block(),
-    ///     ChaCha20 {
-    ///         state: [
-    ///             0x837778ab, 0xe238d763, 0xa67ae21e, 0x5950bb2f,
-    ///             0xc4f2d0c7, 0xfc62bb2f, 0x8fa018fc, 0x3f5ec7b7,
-    ///             0x335271c2, 0xf29489f3, 0xeabda8fc, 0x82e46ebd,
-    ///             0xd19c12b4, 0xb04e16de, 0x9e83d0cb, 0x4e3c50a2
-    ///         ]
-    ///     }
+    ///     ).block().state,
+    ///     [
+    ///         0x837778ab, 0xe238d763, 0xa67ae21e, 0x5950bb2f,
+    ///         0xc4f2d0c7, 0xfc62bb2f, 0x8fa018fc, 0x3f5ec7b7,
+    ///         0x335271c2, 0xf29489f3, 0xeabda8fc, 0x82e46ebd,
+    ///         0xd19c12b4, 0xb04e16de, 0x9e83d0cb, 0x4e3c50a2
+    ///     ]
     /// );
     ///```
     pub fn block(self) -> Self {
@@ -92,4 +150,165 @@ impl ChaCha20 {
 
         out
     }
+
+    /// The serialized keystream of a block, the block function added to the original state.
+    ///
+    /// Panics if the keystream is exhausted, as the counter has wrapped back to block 0.
+    ///
+    ///```
+    ///# use partav2::chacha::*;
+    /// let keystream = ChaCha20::new(
+    ///         [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c],
+    ///         1,
+    ///         [0x09000000, 0x4a000000, 0x00000000]
+    ///     ).keystream();
+    /// assert_eq!(keystream[0..4], [0x10, 0xf1, 0xe7, 0xe4]);
+    ///```
+    pub fn keystream(&self) -> [u8; 64] {
+        assert!(!self.spent, "keystream: self: The keystream is exhausted, and would be reused.");
+
+        let mixed = self.block();
+        let mut out = [0u8; 64];
+
+        for (i, bytes) in out.chunks_exact_mut(4).enumerate() {
+            bytes.copy_from_slice(&mixed.state[i].wrapping_add(self.state[i]).to_le_bytes());
+        }
+
+        out
+    }
+
+    /// Encrypts or decrypts data in place by XORing it with the keystream.
+    ///
+    /// The keystream starts at the current block, and the counter is advanced past every
+    /// block used, including a partial final block whose unused keystream is discarded.
+    ///
+    /// Panics if the 32-bit block counter would wrap around. Using the last block exhausts the
+    /// keystream, and every later call panics until the state is seeked.
+    ///
+    ///```
+    ///# use partav2::chacha::*;
+    /// let mut data = *b"attack at dawn";
+    /// ChaCha20::from_bytes([7; 32], 1, [9; 12]).apply_keystream(&mut data);
+    /// ChaCha20::from_bytes([7; 32], 1, [9; 12]).apply_keystream(&mut data);
+    /// assert_eq!(&data, b"attack at dawn");
+    ///```
+    pub fn apply_keystream(&mut self, data: &mut [u8]) {
+        let blocks = data.len().div_ceil(64) as u64;
+        assert!(!self.spent, "apply_keystream: self: The keystream is exhausted, and would be reused.");
+        assert!(self.counter() as u64 + blocks <= 1 << 32, "apply_keystream: data: The block counter would wrap, reusing the keystream.");
+
+        for chunk in data.chunks_mut(64) {
+            for (byte, key) in chunk.iter_mut().zip(self.keystream()) {
+                *byte ^= key;
+            }
+
+            match self.state[12].checked_add(1) {
+                Some(counter) => self.state[12] = counter,
+                None => {
+                    self.state[12] = 0;
+                    self.spent = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn rfc8439_encryption() {
+        // RFC 8439 section 2.4.2
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let ciphertext = hex(&[
+            "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b",
+            "f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8",
+            "07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736",
+            "5af90bbf74a35be6b40b8eedf2785e42874d"
+        ].concat());
+
+        let mut data = plaintext.to_vec();
+        let mut chacha = ChaCha20::from_bytes(key, 1, nonce);
+        chacha.apply_keystream(&mut data);
+
+        assert_eq!(data, ciphertext);
+        assert_eq!(chacha.counter(), 3);
+
+        ChaCha20::from_bytes(key, 1, nonce).apply_keystream(&mut data);
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn rfc8439_keystream() {
+        // RFC 8439 appendix A.1, test vector 1
+        assert_eq!(
+            ChaCha20::from_bytes([0; 32], 0, [0; 12]).keystream().to_vec(),
+            hex(&[
+                "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7",
+                "da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+            ].concat())
+        );
+    }
+
+    #[test]
+    fn partial_blocks() {
+        let mut whole = vec![0u8; 300];
+        ChaCha20::from_bytes([3; 32], 5, [1; 12]).apply_keystream(&mut whole);
+
+        for len in [0, 1, 63, 64, 65, 127, 128, 299] {
+            let mut part = vec![0u8; len];
+            ChaCha20::from_bytes([3; 32], 5, [1; 12]).apply_keystream(&mut part);
+
+            assert_eq!(part, whole[..len]);
+        }
+
+        let mut chacha = ChaCha20::from_bytes([3; 32], 5, [1; 12]);
+        let mut first = vec![0u8; 100];
+        chacha.apply_keystream(&mut first);
+
+        let mut rest = vec![0u8; 172];
+        chacha.apply_keystream(&mut rest);
+        assert_eq!(rest, whole[128..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn counter_wrap() {
+        let mut data = [0u8; 65];
+        ChaCha20::from_bytes([0; 32], u32::MAX, [0; 12]).apply_keystream(&mut data);
+    }
+
+    #[test]
+    #[should_panic(expected = "apply_keystream: self: The keystream is exhausted, and would be reused.")]
+    fn exhausted_keystream() {
+        let mut chacha = ChaCha20::from_bytes([0; 32], 0, [0; 12]);
+        chacha.seek(u32::MAX);
+
+        // The last block is usable, but only once
+        let mut data = [0u8; 64];
+        chacha.apply_keystream(&mut data);
+        assert_eq!(data, ChaCha20::from_bytes([0; 32], u32::MAX, [0; 12]).keystream());
+
+        chacha.apply_keystream(&mut [0u8; 1]);
+    }
+
+    #[test]
+    fn exhausted_keystream_is_not_reused() {
+        let mut chacha = ChaCha20::from_bytes([0; 32], u32::MAX, [0; 12]);
+        chacha.apply_keystream(&mut [0u8; 64]);
+
+        assert!(std::panic::catch_unwind(|| chacha.keystream()).is_err());
+        assert!(std::panic::catch_unwind(move || chacha.apply_keystream(&mut [])).is_err());
+
+        // Seeking explicitly starts again
+        chacha.seek(0);
+        assert_eq!(chacha.keystream(), ChaCha20::from_bytes([0; 32], 0, [0; 12]).keystream());
+    }
 }
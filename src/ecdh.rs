@@ -0,0 +1,387 @@
+use std::fmt;
+
+use rand::CryptoRng;
+
+use crate::error::Error;
+use crate::rand::Rand;
+use crate::secret::wipe;
+
+/// The u-coordinate of the base point of Curve25519
+pub const BASEPOINT: [u8; 32] = {
+    let mut point = [0u8; 32];
+    point[0] = 9;
+    point
+};
+
+/// The mask of a 51-bit limb
+const LIMB: u64 = (1 << 51) - 1;
+
+/// (A - 2) / 4 for Curve25519
+const A24: u64 = 121665;
+
+/// An element of the field of integers modulo 2^255 - 19, in 51-bit limbs.
+#[derive(Debug, Clone, Copy)]
+struct Field([u64; 5]);
+
+impl Field {
+    const ZERO: Field = Field([0; 5]);
+    const ONE: Field = Field([1, 0, 0, 0, 0]);
+
+    /// 2p, added before subtracting so that limbs never underflow
+    const TWO_P: [u64; 5] = [(LIMB - 18) * 2, LIMB * 2, LIMB * 2, LIMB * 2, LIMB * 2];
+
+    /// Loads 255 little-endian bits, ignoring the top bit.
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+
+        Field([
+            load(0) & LIMB,
+            (load(6) >> 3) & LIMB,
+            (load(12) >> 6) & LIMB,
+            (load(19) >> 1) & LIMB,
+            (load(24) >> 12) & LIMB
+        ])
+    }
+
+    /// Stores the fully reduced element as 32 little-endian bytes.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().carry().0;
+
+        // Subtract p if h >= p, found by whether h + 19 overflows 2^255
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= LIMB;
+        }
+        h[4] &= LIMB;
+
+        let words = [
+            h[0] | (h[1] << 51),
+            (h[1] >> 13) | (h[2] << 38),
+            (h[2] >> 26) | (h[3] << 25),
+            (h[3] >> 39) | (h[4] << 12)
+        ];
+
+        let mut out = [0u8; 32];
+        for (bytes, word) in out.chunks_exact_mut(8).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+
+        out
+    }
+
+    /// Propagates carries so every limb fits in 51 bits, plus a little.
+    fn carry(self) -> Self {
+        let mut h = self.0;
+
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= LIMB;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= LIMB;
+
+        Field(h)
+    }
+
+    fn add(self, other: Self) -> Self {
+        Field(core::array::from_fn(|i| self.0[i] + other.0[i])).carry()
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Field(core::array::from_fn(|i| self.0[i] + Self::TWO_P[i] - other.0[i])).carry()
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let [a0, a1, a2, a3, a4] = self.0.map(|v| v as u128);
+        let [b0, b1, b2, b3, b4] = other.0.map(|v| v as u128);
+        let [b1_19, b2_19, b3_19, b4_19] = [b1 * 19, b2 * 19, b3 * 19, b4 * 19];
+
+        let mut r = [
+            a0 * b0 + a1 * b4_19 + a2 * b3_19 + a3 * b2_19 + a4 * b1_19,
+            a0 * b1 + a1 * b0 + a2 * b4_19 + a3 * b3_19 + a4 * b2_19,
+            a0 * b2 + a1 * b1 + a2 * b0 + a3 * b4_19 + a4 * b3_19,
+            a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * b4_19,
+            a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0
+        ];
+
+        for i in 0..4 {
+            r[i + 1] += r[i] >> 51;
+            r[i] &= LIMB as u128;
+        }
+        r[0] += 19 * (r[4] >> 51);
+        r[4] &= LIMB as u128;
+        r[1] += r[0] >> 51;
+        r[0] &= LIMB as u128;
+
+        Field(r.map(|v| v as u64)).carry()
+    }
+
+    fn square(self) -> Self {
+        self.mul(self)
+    }
+
+    fn mul_small(self, small: u64) -> Self {
+        self.mul(Field([small, 0, 0, 0, 0]))
+    }
+
+    /// The inverse, as x^(p - 2).
+    fn invert(self) -> Self {
+        // p - 2 = 2^255 - 21, whose bits are all set except bits 2 and 4
+        let mut out = Field::ONE;
+        for bit in (0..255).rev() {
+            out = out.square();
+            if bit != 2 && bit != 4 {
+                out = out.mul(self);
+            }
+        }
+
+        out
+    }
+
+    /// Swaps a and b if swap is 1, without branching.
+    fn cswap(swap: u64, a: &mut Self, b: &mut Self) {
+        let mask = 0u64.wrapping_sub(swap);
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+}
+
+/// Clamps a scalar, as required by X25519.
+///
+///```
+///# use partav2::ecdh::*;
+/// let clamped = clamp([0xff; 32]);
+/// assert_eq!(clamped[0], 0xf8);
+/// assert_eq!(clamped[31], 0x7f);
+///```
+pub fn clamp(scalar: [u8; 32]) -> [u8; 32] {
+    let mut out = scalar;
+    out[0] &= 248;
+    out[31] &= 127;
+    out[31] |= 64;
+
+    out
+}
+
+/// The X25519 function (RFC 7748), multiplying the point u by the scalar with a Montgomery ladder.
+///
+///```
+///# use partav2::ecdh::*;
+/// let scalar = [
+///     0xa5, 0x46, 0xe3, 0x6b, 0xf0, 0x52, 0x7c, 0x9d, 0x3b, 0x16, 0x15, 0x4b, 0x82, 0x46, 0x5e, 0xdd,
+///     0x62, 0x14, 0x4c, 0x0a, 0xc1, 0xfc, 0x5a, 0x18, 0x50, 0x6a, 0x22, 0x44, 0xba, 0x44, 0x9a, 0xc4
+/// ];
+/// let u = [
+///     0xe6, 0xdb, 0x68, 0x67, 0x58, 0x30, 0x30, 0xdb, 0x35, 0x94, 0xc1, 0xa4, 0x24, 0xb1, 0x5f, 0x7c,
+///     0x72, 0x66, 0x24, 0xec, 0x26, 0xb3, 0x35, 0x3b, 0x10, 0xa9, 0x03, 0xa6, 0xd0, 0xab, 0x1c, 0x4c
+/// ];
+/// assert_eq!(x25519(scalar, u), [
+///     0xc3, 0xda, 0x55, 0x37, 0x9d, 0xe9, 0xc6, 0x90, 0x8e, 0x94, 0xea, 0x4d, 0xf2, 0x8d, 0x08, 0x4f,
+///     0x32, 0xec, 0xcf, 0x03, 0x49, 0x1c, 0x71, 0xf7, 0x54, 0xb4, 0x07, 0x55, 0x77, 0xa2, 0x85, 0x52
+/// ]);
+///```
+pub fn x25519(scalar: [u8; 32], u: [u8; 32]) -> [u8; 32] {
+    ladder(&scalar, &u)
+}
+
+/// The Montgomery ladder of X25519, reading the scalar in place.
+///
+/// The scalar is clamped as its bits are read, so no copy of it is made.
+fn ladder(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let x1 = Field::from_bytes(u);
+    let (mut x2, mut z2) = (Field::ONE, Field::ZERO);
+    let (mut x3, mut z3) = (x1, Field::ONE);
+    let mut swap = 0;
+
+    for t in (0..255).rev() {
+        // Clamping clears the low three bits and sets bit 254, the ladder never reads bit 255
+        let bit = match t {
+            254 => 1,
+            0..=2 => 0,
+            _ => ((scalar[t / 8] >> (t % 8)) & 1) as u64
+        };
+
+        swap ^= bit;
+        Field::cswap(swap, &mut x2, &mut x3);
+        Field::cswap(swap, &mut z2, &mut z3);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(A24)));
+    }
+
+    Field::cswap(swap, &mut x2, &mut x3);
+    Field::cswap(swap, &mut z2, &mut z3);
+
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// An X25519 private key, the secret scalar.
+///
+/// The scalar is wiped when the key is dropped, and is not printed by `Debug`. It is not `Clone`,
+/// so it cannot be copied by accident, and it is never handed out, only used in place.
+///
+///```
+///# use partav2::ecdh::*;
+/// let private = PrivateKey::from_bytes([9; 32]);
+/// assert_eq!(private.public_key(), x25519([9; 32], BASEPOINT));
+/// assert_eq!(format!("{:?}", private), "PrivateKey");
+///```
+pub struct PrivateKey([u8; 32]);
+
+impl PrivateKey {
+    /// A private key from its scalar bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// The scalar bytes of the key, for the private state of a hybrid request
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// The public key, the scalar times the base point
+    pub fn public_key(&self) -> [u8; 32] {
+        ladder(&self.0, &BASEPOINT)
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PrivateKey")
+    }
+}
+
+/// Generates a new X25519 keypair
+///
+/// keypair() -> (private, public)
+///
+///```
+///# use partav2::ecdh::*;
+/// let (private1, public1) = keypair();
+/// let (private2, public2) = keypair();
+///
+/// assert_eq!(shared(&private1, public2), shared(&private2, public1));
+///```
+pub fn keypair() -> (PrivateKey, [u8; 32]) {
+    keypair_with_rng(&mut Rand::new())
+}
+
+/// Generates a new X25519 keypair from the given generator
+///
+/// keypair_with_rng(rng) -> (private, public)
+pub fn keypair_with_rng(rng: &mut impl CryptoRng) -> (PrivateKey, [u8; 32]) {
+    let mut private = PrivateKey([0u8; 32]);
+    rng.fill_bytes(&mut private.0);
+
+    let public = private.public_key();
+
+    (private, public)
+}
+
+/// Computes the shared secret with a peer's public key
+///
+/// shared(private, public) -> secret
+///
+/// Fails if the public key is a low order point, giving an all zero secret.
+pub fn shared(private: &PrivateKey, public: [u8; 32]) -> Result<[u8; 32], Error> {
+    let secret = ladder(&private.0, &public);
+
+    if secret.iter().fold(0, |acc, b| acc | b) == 0 {
+        return Err(Error::InvalidKey);
+    }
+
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> [u8; 32] {
+        core::array::from_fn(|i| u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap())
+    }
+
+    #[test]
+    fn rfc7748_vectors() {
+        // RFC 7748 section 5.2
+        assert_eq!(
+            x25519(
+                hex("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d"),
+                hex("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493")
+            ),
+            hex("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957")
+        );
+    }
+
+    #[test]
+    fn rfc7748_iterations() {
+        // RFC 7748 section 5.2, iterating k = X25519(k, u), u = old k
+        let mut k = BASEPOINT;
+        let mut u = BASEPOINT;
+
+        for i in 1..=1000 {
+            let next = x25519(k, u);
+            u = k;
+            k = next;
+
+            if i == 1 {
+                assert_eq!(k, hex("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079"));
+            }
+        }
+
+        assert_eq!(k, hex("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51"));
+    }
+
+    #[test]
+    fn rfc7748_diffie_hellman() {
+        // RFC 7748 section 6.1
+        let alice = PrivateKey::from_bytes(hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a"));
+        let bob = PrivateKey::from_bytes(hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb"));
+
+        assert_eq!(alice.public_key(), hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"));
+        assert_eq!(bob.public_key(), hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"));
+
+        let secret = hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(shared(&alice, bob.public_key()), Ok(secret));
+        assert_eq!(shared(&bob, alice.public_key()), Ok(secret));
+    }
+
+    #[test]
+    fn low_order_points() {
+        let (private, _) = keypair();
+
+        assert_eq!(shared(&private, [0; 32]), Err(Error::InvalidKey));
+
+        let mut one = [0; 32];
+        one[0] = 1;
+        assert_eq!(shared(&private, one), Err(Error::InvalidKey));
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// Errors that can occur while generating or parsing key exchange messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The message ended before all of its fields could be read
+    Truncated,
+    /// The header is well formed, but the payload is longer than the message needs
+    TrailingData,
+    /// The frame or ring header is malformed, or disagrees with the payload
+    BadHeader,
+    /// The message was written with an unknown version of the wire format
+    UnsupportedVersion,
+    /// The message is well formed, but not the type of message expected
+    UnexpectedMessage,
+    /// The number of coefficients is not a power of two
+    ///
+    /// Rings are sent as an exponent of two rather than a size, so no message can carry such a ring and nothing in
+    /// the crate returns this. It is kept so that matches written against the full set of errors keep compiling.
+    NonPowerOfTwo,
+    /// A coefficient is not reduced into the range [0, q)
+    CoefficientOutOfRange,
+    /// The ring is well formed, but cannot be used for the key exchange
+    UnsupportedParameters,
+    /// A key or ciphertext does not have the length of any supported parameter set
+    InvalidLength,
+    /// A key failed its integrity check
+    InvalidKey,
+    /// An authentication tag did not match its message
+    AuthenticationFailed
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated => write!(f, "message is truncated"),
+            Error::TrailingData => write!(f, "message has trailing data"),
+            Error::BadHeader => write!(f, "message has a bad header"),
+            Error::UnsupportedVersion => write!(f, "message has an unsupported wire format version"),
+            Error::UnexpectedMessage => write!(f, "message is not of the expected type"),
+            Error::NonPowerOfTwo => write!(f, "polynomial size is not a power of two"),
+            Error::CoefficientOutOfRange => write!(f, "coefficient is out of range of the modulus"),
+            Error::UnsupportedParameters => write!(f, "ring parameters are not supported"),
+            Error::InvalidLength => write!(f, "key or ciphertext has an invalid length"),
+            Error::InvalidKey => write!(f, "key failed its integrity check"),
+            Error::AuthenticationFailed => write!(f, "message failed authentication")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
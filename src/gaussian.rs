@@ -0,0 +1,217 @@
+use std::sync::OnceLock;
+use rand::CryptoRng;
+
+use crate::ring::NegacyclicRing;
+
+/// The standard deviation of the key exchange noise, 8 / sqrt(2 pi)
+pub const SIGMA: f64 = 3.1915382432114616;
+
+/// The tail cut, in standard deviations, beyond which the probability is below the table precision
+const TAIL: f64 = 12.0;
+
+/// A discrete Gaussian distribution centered on zero, sampled with a cumulative distribution table.
+///
+/// Sampling draws 64 bits and compares them against every entry of the table, so it takes the
+/// same time for every output and uses only integer arithmetic.
+///
+/// ```
+///# use partav2::gaussian::*;
+///# use partav2::rand::*;
+/// let gaussian = DiscreteGaussian::new(3.2);
+/// let x = gaussian.sample(&mut Rand::new());
+/// assert!(x.abs() <= gaussian.tail());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscreteGaussian {
+    sigma: f64,
+    tail: i32,
+
+    /// 2^64 * P(X <= i - tail), for every i in 0..2 * tail
+    cdt: Vec<u64>
+}
+
+impl DiscreteGaussian {
+    /// Builds the table for a standard deviation of sigma.
+    ///
+    /// Panics if sigma is not between 0.5 and 1000.
+    pub fn new(sigma: f64) -> Self {
+        assert!((0.5..=1000.0).contains(&sigma), "DiscreteGaussian::new: sigma: Must be between 0.5 and 1000.");
+
+        let tail = (TAIL * sigma).ceil() as i32;
+
+        let weights: Vec<f64> = (-tail..=tail).map(|x| (-(x as f64).powi(2) / (2.0 * sigma * sigma)).exp()).collect();
+        let total: f64 = weights.iter().sum();
+
+        let mut cdt = vec![];
+        let mut cumulative = 0.0;
+        for weight in &weights[..weights.len() - 1] {
+            cumulative += weight / total;
+            cdt.push((cumulative * 2f64.powi(64)) as u64);
+        }
+
+        Self {
+            sigma,
+            tail,
+            cdt
+        }
+    }
+
+    /// The distribution of the key exchange noise, with a standard deviation of SIGMA.
+    pub fn standard() -> &'static Self {
+        static STANDARD: OnceLock<DiscreteGaussian> = OnceLock::new();
+
+        STANDARD.get_or_init(|| Self::new(SIGMA))
+    }
+
+    /// The standard deviation
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    /// The largest magnitude that can be sampled
+    pub fn tail(&self) -> i32 {
+        self.tail
+    }
+
+    /// Samples an integer in [-tail, tail], in constant time.
+    pub fn sample(&self, rng: &mut impl CryptoRng) -> i32 {
+        let r = rng.next_u64() as u128;
+
+        // Counts the entries at or below r, using the borrow out of r - entry
+        let mut index = 0;
+        for entry in &self.cdt {
+            index += (((r | 1 << 64) - *entry as u128) >> 64) as i32;
+        }
+
+        index - self.tail
+    }
+}
+
+impl NegacyclicRing {
+    /// Discrete Gaussian polynomial sampling over a negacyclic ring, from the given generator.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::gaussian::*;
+    ///# use partav2::rand::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// let poly = ring.sample_gaussian(&DiscreteGaussian::new(2.0), &mut Rand::new());
+    ///
+    /// assert!(poly.iter().all(|v| *v <= 24 || *v >= 7681 - 24));
+    /// ```
+    pub fn sample_gaussian(&self, gaussian: &DiscreteGaussian, rng: &mut impl CryptoRng) -> Vec<i32> {
+        (0..self.size()).map(|_| self.reduce(gaussian.sample(rng))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::Rand;
+    use rand::RngCore;
+
+    /// A generator that always returns the same value
+    struct Fixed(u64);
+
+    impl RngCore for Fixed {
+        fn next_u32(&mut self) -> u32 {
+            self.0 as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            for (i, byte) in dst.iter_mut().enumerate() {
+                *byte = self.0.to_le_bytes()[i % 8];
+            }
+        }
+    }
+
+    impl CryptoRng for Fixed {}
+
+    /// Chi-squared statistic of the samples against the target distribution, with the tails
+    /// pooled so every bin expects at least 5 samples, and the number of degrees of freedom.
+    fn chi_squared(gaussian: &DiscreteGaussian, samples: usize, seed: u8) -> (f64, usize) {
+        let mut rng = Rand::from_seed([seed; 32]);
+        let tail = gaussian.tail();
+
+        let mut counts = vec![0usize; 2 * tail as usize + 1];
+        for _ in 0..samples {
+            counts[(gaussian.sample(&mut rng) + tail) as usize] += 1;
+        }
+
+        let weights: Vec<f64> = (-tail..=tail).map(|x| (-(x as f64).powi(2) / (2.0 * gaussian.sigma().powi(2))).exp()).collect();
+        let total: f64 = weights.iter().sum();
+
+        let mut bins = vec![];
+        let (mut observed, mut expected) = (0.0, 0.0);
+        for (count, weight) in counts.iter().zip(weights) {
+            observed += *count as f64;
+            expected += weight / total * samples as f64;
+
+            if expected >= 5.0 {
+                bins.push((observed, expected));
+                (observed, expected) = (0.0, 0.0);
+            }
+        }
+        bins.last_mut().unwrap().0 += observed;
+        bins.last_mut().unwrap().1 += expected;
+
+        let statistic = bins.iter().map(|(o, e)| (o - e).powi(2) / e).sum();
+
+        (statistic, bins.len() - 1)
+    }
+
+    #[test]
+    fn matches_target_distribution() {
+        // Critical values of chi-squared at p = 0.001
+        let critical = |df: usize| match df {
+            0..=10 => 29.6,
+            11..=20 => 45.3,
+            21..=30 => 59.7,
+            _ => 86.7
+        };
+
+        for (sigma, seed) in [(SIGMA, 1), (1.0, 2), (5.5, 3)] {
+            let (statistic, df) = chi_squared(&DiscreteGaussian::new(sigma), 200000, seed);
+            assert!(df <= 50);
+            assert!(statistic < critical(df), "sigma {}: chi-squared {} with {} degrees of freedom", sigma, statistic, df);
+        }
+    }
+
+    #[test]
+    fn moments() {
+        let gaussian = DiscreteGaussian::standard();
+        let mut rng = Rand::from_seed([4; 32]);
+
+        let samples: Vec<f64> = (0..200000).map(|_| gaussian.sample(&mut rng) as f64).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+
+        assert!(mean.abs() < 0.05, "mean {}", mean);
+        assert!((variance.sqrt() - SIGMA).abs() < 0.05, "standard deviation {}", variance.sqrt());
+    }
+
+    #[test]
+    fn table_bounds() {
+        let gaussian = DiscreteGaussian::new(SIGMA);
+
+        assert_eq!(gaussian.tail(), 39);
+        assert_eq!(gaussian.cdt.len(), 78);
+        assert!(gaussian.cdt.windows(2).all(|w| w[0] <= w[1]));
+
+        // Larger draws never give smaller values
+        let mut last = -gaussian.tail();
+        for r in (0..=u16::MAX).map(|r| (r as u64) << 48 | 0xffff_ffff_ffff) {
+            let x = gaussian.sample(&mut Fixed(r));
+            assert!(x >= last);
+            last = x;
+        }
+
+        assert_eq!(gaussian.sample(&mut Fixed(1 << 63)), 0);
+        assert!(gaussian.sample(&mut Fixed(0)) < -8);
+        assert!(gaussian.sample(&mut Fixed(u64::MAX)) > 8);
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use crate::sha3;
+
+/// The default size of a shared secret in bytes
+pub const SHARED_SECRET_SIZE: usize = 32;
+
+/// Separates key exchange secrets from any other use of SHAKE256
+const DOMAIN: &[u8] = b"PartaV2 shared secret";
+
+/// A secret shared between both sides of the key exchange.
+///
+/// Comparisons are constant time, and the secret is not printed by `Debug`.
+#[derive(Clone)]
+pub struct SharedSecret {
+    bytes: Vec<u8>
+}
+
+impl SharedSecret {
+    /// The bytes of the secret
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The length of the secret in bytes
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether the secret is empty
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl AsRef<[u8]> for SharedSecret {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl PartialEq for SharedSecret {
+    fn eq(&self, other: &Self) -> bool {
+        if self.bytes.len() != other.bytes.len() {
+            return false;
+        }
+
+        let mut difference = 0u8;
+        for (a, b) in self.bytes.iter().zip(&other.bytes) {
+            difference |= a ^ b;
+        }
+
+        difference == 0
+    }
+}
+
+impl Eq for SharedSecret {}
+
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SharedSecret({} bytes)", self.bytes.len())
+    }
+}
+
+/// Absorbs a length prefixed field, so that field boundaries are unambiguous
+fn absorb_field(xof: &mut sha3::Shake, field: &[u8]) {
+    xof.absorb(&(field.len() as u64).to_le_bytes());
+    xof.absorb(field);
+}
+
+/// The hash of a public message, binding a secret to the transcript
+///
+/// ```
+///# use partav2::kdf::*;
+/// assert_eq!(transcript_hash(b"request").len(), 32);
+/// ```
+pub fn transcript_hash(message: &[u8]) -> Vec<u8> {
+    sha3::digest(256, message)
+}
+
+/// Derives a shared secret of len bytes from the raw secrets and the transcript hashes.
+///
+/// SHAKE256 over the domain, the output length, every raw secret and both transcript hashes.
+/// A hybrid exchange passes one raw secret per exchange, so the result is secure while any of them is.
+///
+/// ```
+///# use partav2::kdf::*;
+/// let secret = derive(&[&[1, 2, 3]], &transcript_hash(b"request"), &transcript_hash(b"response"), 32);
+/// assert_eq!(secret.len(), 32);
+///
+/// assert_ne!(secret, derive(&[&[1, 2, 3]], &transcript_hash(b"request"), &transcript_hash(b"other"), 32));
+/// assert_ne!(secret, derive(&[&[1, 2], &[3]], &transcript_hash(b"request"), &transcript_hash(b"response"), 32));
+/// assert_ne!(secret.as_bytes(), &derive(&[&[1, 2, 3]], &transcript_hash(b"request"), &transcript_hash(b"response"), 64).as_bytes()[..32]);
+/// ```
+pub fn derive(secrets: &[&[u8]], request: &[u8], response: &[u8], len: usize) -> SharedSecret {
+    let mut xof = sha3::Shake::new(256);
+
+    absorb_field(&mut xof, DOMAIN);
+    xof.absorb(&(len as u64).to_le_bytes());
+    xof.absorb(&(secrets.len() as u64).to_le_bytes());
+    for secret in secrets {
+        absorb_field(&mut xof, secret);
+    }
+    absorb_field(&mut xof, request);
+    absorb_field(&mut xof, response);
+
+    let mut bytes = vec![0; len];
+    xof.squeeze(&mut bytes);
+
+    SharedSecret {
+        bytes
+    }
+}
@@ -0,0 +1,2 @@
+/// ML-KEM (FIPS 203), the standardised form of Kyber
+pub mod mlkem;
@@ -0,0 +1,880 @@
+use std::sync::OnceLock;
+use rand::CryptoRng;
+
+use crate::error::Error;
+use crate::kdf::{SHARED_SECRET_SIZE, SharedSecret, derive, transcript_hash};
+use crate::rand::Rand;
+use crate::ring::NegacyclicRing;
+use crate::secret::wipe;
+use crate::sha3;
+use crate::wire::{Frame, MessageType};
+use crate::{PrivateState, TRANSCRIPT_SIZE, check_size};
+
+/// The ring (X^256 + 1)_3329 that ML-KEM works over
+///
+/// It has no 512th root of unity, so ML-KEM uses its own incomplete NTT rather than [`NegacyclicRing::ntt`],
+/// but every coefficient is still added, subtracted and multiplied with the ring's branch-free Barrett
+/// arithmetic, so no secret is ever divided by q.
+pub const RING: NegacyclicRing = NegacyclicRing::new(8, 3329);
+
+const N: usize = 256;
+const Q: i32 = RING.modulus;
+
+/// The primitive 256th root of unity used by the ML-KEM NTT
+const ZETA: i32 = 17;
+
+/// The inverse of 128 with respect to the modulus
+const INVERSE_128: i32 = 3303;
+
+/// ceil(2^32 / q), so that (t * COMPRESS_FACTOR) >> 32 = floor(t / q) for every numerator of [`compress`]
+const COMPRESS_FACTOR: u64 = 1290168;
+
+/// The ML-KEM parameter sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Equivalent to AES-128
+    MlKem512,
+    /// Equivalent to AES-192
+    MlKem768,
+    /// Equivalent to AES-256
+    MlKem1024
+}
+
+/// The parameters of the K-PKE public key encryption scheme underlying ML-KEM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KPke {
+    pub k: usize,
+    pub eta1: usize,
+    pub eta2: usize,
+    pub du: usize,
+    pub dv: usize
+}
+
+impl Level {
+    /// Every parameter set, from least to most secure
+    pub const ALL: [Level; 3] = [Level::MlKem512, Level::MlKem768, Level::MlKem1024];
+
+    /// The ID of the level in the parameter set field of a framed message, its size in bits
+    ///
+    ///```
+    ///# use partav2::kem::mlkem::*;
+    /// assert_eq!(Level::MlKem768.id(), 768);
+    /// assert_eq!(Level::from_id(768), Some(Level::MlKem768));
+    ///```
+    pub fn id(&self) -> u16 {
+        match self {
+            Level::MlKem512 => 512,
+            Level::MlKem768 => 768,
+            Level::MlKem1024 => 1024
+        }
+    }
+
+    /// The level with the given ID, if there is one
+    pub fn from_id(id: u16) -> Option<Self> {
+        Level::ALL.into_iter().find(|level| level.id() == id)
+    }
+
+    /// Returns the K-PKE parameters of the level
+    ///
+    ///```
+    ///# use partav2::kem::mlkem::*;
+    /// assert_eq!(Level::MlKem768.pke(), KPke { k: 3, eta1: 2, eta2: 2, du: 10, dv: 4 });
+    ///```
+    pub fn pke(&self) -> KPke {
+        match self {
+            Level::MlKem512 => KPke { k: 2, eta1: 3, eta2: 2, du: 10, dv: 4 },
+            Level::MlKem768 => KPke { k: 3, eta1: 2, eta2: 2, du: 10, dv: 4 },
+            Level::MlKem1024 => KPke { k: 4, eta1: 2, eta2: 2, du: 11, dv: 5 }
+        }
+    }
+
+    /// The size of an encapsulation key in bytes
+    pub fn encapsulation_key_size(&self) -> usize {
+        384 * self.pke().k + 32
+    }
+
+    /// The size of a decapsulation key in bytes
+    pub fn decapsulation_key_size(&self) -> usize {
+        768 * self.pke().k + 96
+    }
+
+    /// The size of a ciphertext in bytes
+    pub fn ciphertext_size(&self) -> usize {
+        let pke = self.pke();
+        32 * (pke.du * pke.k + pke.dv)
+    }
+}
+
+/// Precomputed powers of the root of unity
+struct Tables {
+    /// zeta ^ BitRev7(i)
+    zetas: [i32; 128],
+    /// zeta ^ (2 BitRev7(i) + 1)
+    gammas: [i32; 128]
+}
+
+static TABLES: OnceLock<Tables> = OnceLock::new();
+
+fn tables() -> &'static Tables {
+    TABLES.get_or_init(|| {
+        let mut zetas = [0; 128];
+        let mut gammas = [0; 128];
+
+        for i in 0..128 {
+            let reversed = NegacyclicRing::bit_reverse(i as i32, 7);
+            zetas[i] = RING.power(ZETA, reversed);
+            gammas[i] = RING.power(ZETA, 2 * reversed + 1);
+        }
+
+        Tables {
+            zetas,
+            gammas
+        }
+    })
+}
+
+/// Hash function H, SHA3-256
+fn h(input: &[u8]) -> Vec<u8> {
+    sha3::digest(256, input)
+}
+
+/// Hash function G, SHA3-512, split into two 32 byte halves
+fn g(input: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let out = sha3::digest(512, input);
+
+    (out[0..32].try_into().unwrap(), out[32..64].try_into().unwrap())
+}
+
+/// Hash function J, SHAKE256 with 32 bytes of output
+fn j(input: &[u8]) -> Vec<u8> {
+    sha3::shake(256, input, 32)
+}
+
+/// Pseudorandom function PRF, SHAKE256 of the seed and a single byte
+fn prf(eta: usize, s: &[u8; 32], b: u8) -> Vec<u8> {
+    let mut input = s.to_vec();
+    input.push(b);
+
+    sha3::shake(256, &input, 64 * eta)
+}
+
+/// Rounds (2^d / q) x to the nearest integer mod 2^d, for x reduced into [0, q) and d at most 11.
+///
+/// It is applied to secrets, so the division by q is a multiplication and a shift rather than a
+/// division, whose time can depend on its operands (KyberSlash).
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// assert_eq!(compress(1665, 1), 1);
+/// assert_eq!(compress(832, 1), 0);
+/// assert_eq!(compress(3328, 4), 0);
+///```
+pub fn compress(x: i32, d: usize) -> i32 {
+    let t = ((x as u64) << d) + Q as u64 / 2;
+
+    ((t * COMPRESS_FACTOR) >> 32) as i32 & ((1 << d) - 1)
+}
+
+/// Rounds (q / 2^d) y to the nearest integer.
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// assert_eq!(decompress(1, 1), 1665);
+/// assert_eq!(decompress(compress(1000, 10), 10), 1001);
+///```
+pub fn decompress(y: i32, d: usize) -> i32 {
+    (Q * y + (1 << (d - 1))) >> d
+}
+
+/// Encodes 256 d-bit integers into 32d bytes (ByteEncode).
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let mut poly = vec![0; 256];
+/// poly[0] = 3;
+/// poly[1] = 1;
+/// assert_eq!(encode(&poly, 2)[0], 0b0111);
+///```
+pub fn encode(poly: &[i32], d: usize) -> Vec<u8> {
+    let mut out = vec![0u8; 32 * d];
+
+    for (i, c) in poly.iter().enumerate() {
+        for b in 0..d {
+            let bit = i * d + b;
+            out[bit / 8] |= (((c >> b) & 1) as u8) << (bit % 8);
+        }
+    }
+
+    out
+}
+
+/// Decodes 32d bytes into 256 d-bit integers (ByteDecode), without reducing them.
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let poly = (0..256).map(|i| i * 13).collect::<Vec<i32>>();
+/// assert_eq!(decode(&encode(&poly, 12), 12), poly);
+///```
+pub fn decode(bytes: &[u8], d: usize) -> Vec<i32> {
+    (0..N).map(|i| {
+        (0..d).map(|b| {
+            let bit = i * d + b;
+            (((bytes[bit / 8] >> (bit % 8)) & 1) as i32) << b
+        }).sum()
+    }).collect()
+}
+
+/// Samples a polynomial in NTT space from the seed, using SHAKE128 and rejection sampling (SampleNTT).
+pub fn parse(seed: &[u8]) -> Vec<i32> {
+    let mut xof = sha3::Shake::new(128);
+    xof.absorb(seed);
+
+    let mut out = Vec::with_capacity(N);
+    let mut c = [0u8; 3];
+
+    while out.len() < N {
+        xof.squeeze(&mut c);
+
+        let d1 = c[0] as i32 + 256 * (c[1] as i32 % 16);
+        let d2 = c[1] as i32 / 16 + 16 * c[2] as i32;
+
+        if d1 < Q {
+            out.push(d1);
+        }
+        if d2 < Q && out.len() < N {
+            out.push(d2);
+        }
+    }
+
+    out
+}
+
+/// Samples a polynomial from the centered binomial distribution with parameter eta (SamplePolyCBD).
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let poly = cbd(&[0b00000001; 128], 2);
+/// assert_eq!(poly[0], 1);
+/// assert_eq!(poly[1], 0);
+///```
+pub fn cbd(bytes: &[u8], eta: usize) -> Vec<i32> {
+    let bit = |i: usize| ((bytes[i / 8] >> (i % 8)) & 1) as i32;
+
+    (0..N).map(|i| {
+        let a: i32 = (0..eta).map(|j| bit(2 * i * eta + j)).sum();
+        let b: i32 = (0..eta).map(|j| bit(2 * i * eta + eta + j)).sum();
+
+        RING.sub_mod(a, b)
+    }).collect()
+}
+
+/// The ML-KEM number theoretic transform, to 128 polynomials of degree one.
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let poly = (0..256).map(|i| i * 7).collect::<Vec<i32>>();
+/// assert_eq!(intt(&ntt(&poly)), poly);
+///```
+pub fn ntt(poly: &[i32]) -> Vec<i32> {
+    let zetas = &tables().zetas;
+
+    let mut f = poly.to_vec();
+    let mut i = 1;
+
+    let mut len = 128;
+    while len >= 2 {
+        for start in (0..N).step_by(2 * len) {
+            let zeta = zetas[i];
+            i += 1;
+
+            for j in start..start + len {
+                let t = RING.mul_mod(zeta, f[j + len]);
+                f[j + len] = RING.sub_mod(f[j], t);
+                f[j] = RING.add_mod(f[j], t);
+            }
+        }
+
+        len /= 2;
+    }
+
+    f
+}
+
+/// The inverse of the ML-KEM number theoretic transform.
+pub fn intt(poly: &[i32]) -> Vec<i32> {
+    let zetas = &tables().zetas;
+
+    let mut f = poly.to_vec();
+    let mut i = 127;
+
+    let mut len = 2;
+    while len <= 128 {
+        for start in (0..N).step_by(2 * len) {
+            let zeta = zetas[i];
+            i -= 1;
+
+            for j in start..start + len {
+                let t = f[j];
+                f[j] = RING.add_mod(t, f[j + len]);
+                f[j + len] = RING.mul_mod(zeta, RING.sub_mod(f[j + len], t));
+            }
+        }
+
+        len *= 2;
+    }
+
+    f.into_iter().map(|v| RING.mul_mod(v, INVERSE_128)).collect()
+}
+
+/// Multiplication of polynomials in NTT space (MultiplyNTTs).
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let mut a = vec![0; 256];
+/// let mut b = vec![0; 256];
+/// a[1] = 1;
+/// b[255] = 1;
+/// // X * X^255 = X^256 = -1
+/// let mut expected = vec![0; 256];
+/// expected[0] = 3328;
+/// assert_eq!(intt(&multiply_ntts(&ntt(&a), &ntt(&b))), expected);
+///```
+pub fn multiply_ntts(a: &[i32], b: &[i32]) -> Vec<i32> {
+    let gammas = &tables().gammas;
+
+    let mut out = vec![0; N];
+
+    for i in 0..N / 2 {
+        let (a0, a1) = (a[2 * i], a[2 * i + 1]);
+        let (b0, b1) = (b[2 * i], b[2 * i + 1]);
+
+        out[2 * i] = RING.add_mod(RING.mul_mod(a0, b0), RING.mul_mod(RING.mul_mod(a1, b1), gammas[i]));
+        out[2 * i + 1] = RING.add_mod(RING.mul_mod(a0, b1), RING.mul_mod(a1, b0));
+    }
+
+    out
+}
+
+fn add(a: &[i32], b: &[i32]) -> Vec<i32> {
+    a.iter().zip(b).map(|(&a, &b)| RING.add_mod(a, b)).collect()
+}
+
+fn sub(a: &[i32], b: &[i32]) -> Vec<i32> {
+    a.iter().zip(b).map(|(&a, &b)| RING.sub_mod(a, b)).collect()
+}
+
+/// The dot product of two vectors of NTT space polynomials
+fn dot(a: &[Vec<i32>], b: &[Vec<i32>]) -> Vec<i32> {
+    a.iter().zip(b).fold(vec![0; N], |acc, (a, b)| add(&acc, &multiply_ntts(a, b)))
+}
+
+impl KPke {
+    /// Expands the seed rho into the matrix A, in NTT space.
+    fn matrix(&self, rho: &[u8]) -> Vec<Vec<Vec<i32>>> {
+        (0..self.k).map(|i| {
+            (0..self.k).map(|j| {
+                let mut seed = rho.to_vec();
+                seed.push(j as u8);
+                seed.push(i as u8);
+
+                parse(&seed)
+            }).collect()
+        }).collect()
+    }
+
+    /// Samples k polynomials from the centered binomial distribution, advancing the counter.
+    fn sample_vector(&self, eta: usize, sigma: &[u8; 32], counter: &mut u8) -> Vec<Vec<i32>> {
+        (0..self.k).map(|_| {
+            let poly = cbd(&prf(eta, sigma, *counter), eta);
+            *counter += 1;
+
+            poly
+        }).collect()
+    }
+
+    /// Generates an encryption and decryption key from a seed (K-PKE.KeyGen).
+    ///
+    /// keygen(d) -> (ek, dk)
+    pub fn keygen(&self, d: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+        let mut seed = d.to_vec();
+        seed.push(self.k as u8);
+        let (rho, sigma) = g(&seed);
+
+        let a = self.matrix(&rho);
+
+        let mut counter = 0;
+        let s: Vec<Vec<i32>> = self.sample_vector(self.eta1, &sigma, &mut counter).iter().map(|p| ntt(p)).collect();
+        let e: Vec<Vec<i32>> = self.sample_vector(self.eta1, &sigma, &mut counter).iter().map(|p| ntt(p)).collect();
+
+        let mut ek = vec![];
+        for i in 0..self.k {
+            ek.extend(encode(&add(&dot(&a[i], &s), &e[i]), 12));
+        }
+        ek.extend(rho);
+
+        let dk = s.iter().flat_map(|p| encode(p, 12)).collect();
+
+        (ek, dk)
+    }
+
+    /// Encrypts a 32 byte message with the randomness r (K-PKE.Encrypt).
+    ///
+    /// The encryption key must already have passed its modulus check.
+    pub fn encrypt(&self, ek: &[u8], m: &[u8; 32], r: &[u8; 32]) -> Vec<u8> {
+        let t: Vec<Vec<i32>> = ek[..384 * self.k].chunks_exact(384).map(|c| decode(c, 12)).collect();
+        let a = self.matrix(&ek[384 * self.k..]);
+
+        let mut counter = 0;
+        let y: Vec<Vec<i32>> = self.sample_vector(self.eta1, r, &mut counter).iter().map(|p| ntt(p)).collect();
+        let e1 = self.sample_vector(self.eta2, r, &mut counter);
+        let e2 = cbd(&prf(self.eta2, r, counter), self.eta2);
+
+        let mut c = vec![];
+        for i in 0..self.k {
+            let column: Vec<Vec<i32>> = (0..self.k).map(|j| a[j][i].clone()).collect();
+            let u = add(&intt(&dot(&column, &y)), &e1[i]);
+
+            c.extend(encode(&u.into_iter().map(|v| compress(v, self.du)).collect::<Vec<i32>>(), self.du));
+        }
+
+        let mu: Vec<i32> = decode(m, 1).into_iter().map(|v| decompress(v, 1)).collect();
+        let v = add(&add(&intt(&dot(&t, &y)), &e2), &mu);
+
+        c.extend(encode(&v.into_iter().map(|v| compress(v, self.dv)).collect::<Vec<i32>>(), self.dv));
+
+        c
+    }
+
+    /// Decrypts a ciphertext to the 32 byte message (K-PKE.Decrypt).
+    pub fn decrypt(&self, dk: &[u8], c: &[u8]) -> [u8; 32] {
+        let (c1, c2) = c.split_at(32 * self.du * self.k);
+
+        let u: Vec<Vec<i32>> = c1.chunks_exact(32 * self.du).map(|c| ntt(&decode(c, self.du).into_iter().map(|v| decompress(v, self.du)).collect::<Vec<i32>>())).collect();
+        let v: Vec<i32> = decode(c2, self.dv).into_iter().map(|v| decompress(v, self.dv)).collect();
+
+        let s: Vec<Vec<i32>> = dk.chunks_exact(384).map(|c| decode(c, 12)).collect();
+
+        let w = sub(&v, &intt(&dot(&s, &u)));
+
+        encode(&w.into_iter().map(|v| compress(v, 1)).collect::<Vec<i32>>(), 1).try_into().unwrap()
+    }
+}
+
+/// 32 random bytes from a random generator
+fn random_seed(rng: &mut impl CryptoRng) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+
+    seed
+}
+
+/// Finds the level with the given encapsulation key size
+fn level_of_encapsulation_key(ek: &[u8]) -> Result<Level, Error> {
+    Level::ALL.into_iter().find(|level| level.encapsulation_key_size() == ek.len()).ok_or(Error::InvalidLength)
+}
+
+/// Finds the level with the given decapsulation key size
+fn level_of_decapsulation_key(dk: &[u8]) -> Result<Level, Error> {
+    Level::ALL.into_iter().find(|level| level.decapsulation_key_size() == dk.len()).ok_or(Error::InvalidLength)
+}
+
+/// Deterministically generates a keypair from the seeds d and z (ML-KEM.KeyGen_internal).
+///
+/// keygen_internal(level, d, z) -> (decapsulation, encapsulation)
+pub fn keygen_internal(level: Level, d: &[u8; 32], z: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+    let (ek, dk_pke) = level.pke().keygen(d);
+
+    let mut dk = dk_pke;
+    dk.extend(&ek);
+    dk.extend(h(&ek));
+    dk.extend(z);
+
+    (dk, ek)
+}
+
+/// Deterministically encapsulates a key with the message m (ML-KEM.Encaps_internal).
+///
+/// encapsulate_internal(encapsulation, m) -> (key, ciphertext)
+///
+/// Fails if the encapsulation key has the wrong length, or does not pass its modulus check.
+pub fn encapsulate_internal(ek: Vec<u8>, m: &[u8; 32]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let level = level_of_encapsulation_key(&ek)?;
+    let pke = level.pke();
+
+    for chunk in ek[..384 * pke.k].chunks_exact(384) {
+        if decode(chunk, 12).into_iter().any(|v| v >= Q) {
+            return Err(Error::CoefficientOutOfRange);
+        }
+    }
+
+    let mut seed = m.to_vec();
+    seed.extend(h(&ek));
+    let (key, r) = g(&seed);
+
+    let ciphertext = pke.encrypt(&ek, m, &r);
+
+    Ok((key.to_vec(), ciphertext))
+}
+
+/// Generates a new ML-KEM keypair
+///
+/// keygen(level) -> (decapsulation, encapsulation)
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let (dk, ek) = keygen(Level::MlKem768);
+///```
+pub fn keygen(level: Level) -> (Vec<u8>, Vec<u8>) {
+    keygen_with_rng(level, &mut Rand::new())
+}
+
+/// Generates a new ML-KEM keypair from the given generator
+///
+/// keygen_with_rng(level, rng) -> (decapsulation, encapsulation)
+///
+///```
+///# use partav2::kem::mlkem::*;
+///# use partav2::rand::*;
+/// let (dk, ek) = keygen_with_rng(Level::MlKem768, &mut Rand::from_seed([1; 32]));
+/// assert_eq!((dk, ek), keygen_with_rng(Level::MlKem768, &mut Rand::from_seed([1; 32])));
+///```
+pub fn keygen_with_rng(level: Level, rng: &mut impl CryptoRng) -> (Vec<u8>, Vec<u8>) {
+    let d = random_seed(rng);
+    let z = random_seed(rng);
+
+    keygen_internal(level, &d, &z)
+}
+
+/// Encapsulates a new shared key to an encapsulation key
+///
+/// encapsulate(encapsulation) -> (key, ciphertext)
+///
+/// The level is determined by the length of the encapsulation key.
+///
+///```
+///# use partav2::kem::mlkem::*;
+///# let (dk, ek) = keygen(Level::MlKem768);
+/// let (key2, ciphertext) = encapsulate(ek).unwrap();
+///```
+pub fn encapsulate(ek: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    encapsulate_with_rng(ek, &mut Rand::new())
+}
+
+/// Encapsulates a new shared key to an encapsulation key, from the given generator
+///
+/// encapsulate_with_rng(encapsulation, rng) -> (key, ciphertext)
+pub fn encapsulate_with_rng(ek: Vec<u8>, rng: &mut impl CryptoRng) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    encapsulate_internal(ek, &random_seed(rng))
+}
+
+/// Decapsulates the shared key from a ciphertext
+///
+/// decapsulate(decapsulation, ciphertext) -> key
+///
+/// An invalid ciphertext of the correct length implicitly rejects, producing an unrelated key.
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let (dk, ek) = keygen(Level::MlKem768);
+/// let (key2, ciphertext) = encapsulate(ek).unwrap();
+/// let key1 = decapsulate(dk, ciphertext).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn decapsulate(dk: Vec<u8>, ciphertext: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let level = level_of_decapsulation_key(&dk)?;
+    let pke = level.pke();
+
+    if ciphertext.len() != level.ciphertext_size() {
+        return Err(Error::InvalidLength);
+    }
+
+    let dk_pke = &dk[..384 * pke.k];
+    let ek = &dk[384 * pke.k..768 * pke.k + 32];
+    let hash = &dk[768 * pke.k + 32..768 * pke.k + 64];
+    let z = &dk[768 * pke.k + 64..];
+
+    if h(ek) != hash {
+        return Err(Error::InvalidKey);
+    }
+
+    let m = pke.decrypt(dk_pke, &ciphertext);
+
+    let mut seed = m.to_vec();
+    seed.extend(hash);
+    let (key, r) = g(&seed);
+
+    let mut rejection = z.to_vec();
+    rejection.extend(&ciphertext);
+    let rejected = j(&rejection);
+
+    let reencrypted = pke.encrypt(ek, &m, &r);
+
+    // Select between the keys without branching on whether the ciphertexts match
+    let mut difference = 0u8;
+    for (a, b) in reencrypted.iter().zip(&ciphertext) {
+        difference |= a ^ b;
+    }
+    let mask = ((difference as u16).wrapping_sub(1) >> 8) as u8;
+
+    Ok(key.iter().zip(rejected).map(|(k, r)| (k & mask) | (r & !mask)).collect())
+}
+
+/// Generates a new ML-KEM keypair, framing the encapsulation key as a request
+///
+/// request(level) -> (private, request)
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let (private1, public1) = request(Level::MlKem768);
+///```
+pub fn request(level: Level) -> (PrivateState, Vec<u8>) {
+    request_with_rng(level, &mut Rand::new())
+}
+
+/// Generates a new ML-KEM keypair from the given generator, framing the encapsulation key as a request
+///
+/// request_with_rng(level, rng) -> (private, request)
+pub fn request_with_rng(level: Level, rng: &mut impl CryptoRng) -> (PrivateState, Vec<u8>) {
+    let (mut dk, ek) = keygen_with_rng(level, rng);
+
+    let public = Frame {
+        message_type: MessageType::KemRequest,
+        parameters: level.id(),
+        payload: ek
+    }.encode();
+
+    // The request is needed again to bind the secret to the transcript
+    let private = PrivateState::new(&public, &mut [&mut dk]);
+
+    (private, public)
+}
+
+/// Encapsulates a key to a framed request, framing the ciphertext as the response
+///
+/// respond(request) -> (key, response)
+///
+/// The key is a 32 byte secret, bound to both the request and the response.
+///
+/// Fails if the request is malformed, or for an unknown level.
+///
+///```
+///# use partav2::kem::mlkem::*;
+///# let (private1, public1) = request(Level::MlKem768);
+/// let (key2, public2) = respond(public1).unwrap();
+/// assert_eq!(key2.len(), 32);
+///```
+pub fn respond(request: Vec<u8>) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_with_rng(request, &mut Rand::new())
+}
+
+/// Encapsulates a key to a framed request from the given generator, framing the ciphertext as the response
+///
+/// respond_with_rng(request, rng) -> (key, response)
+pub fn respond_with_rng(request: Vec<u8>, rng: &mut impl CryptoRng) -> Result<(SharedSecret, Vec<u8>), Error> {
+    let frame = Frame::decode_expecting(&request, MessageType::KemRequest)?;
+    let level = Level::from_id(frame.parameters).ok_or(Error::UnsupportedParameters)?;
+    check_size(&frame.payload, level.encapsulation_key_size())?;
+
+    let (mut key1, ciphertext) = encapsulate_with_rng(frame.payload, rng)?;
+
+    let public = Frame {
+        message_type: MessageType::KemResponse,
+        parameters: level.id(),
+        payload: ciphertext
+    }.encode();
+
+    let key = derive(&[&key1], &transcript_hash(&request), &transcript_hash(&public), SHARED_SECRET_SIZE);
+    wipe(&mut key1);
+
+    Ok((key, public))
+}
+
+/// Decapsulates the key from a framed response
+///
+/// finalise(private, response) -> key
+///
+/// Fails if the response is malformed, or is for a different level than the request.
+///
+///```
+///# use partav2::kem::mlkem::*;
+/// let (private1, public1) = request(Level::MlKem768);
+/// let (key2, public2) = respond(public1).unwrap();
+/// let key1 = finalise(private1, public2).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise(private: PrivateState, response: Vec<u8>) -> Result<SharedSecret, Error> {
+    let frame = Frame::decode_expecting(&response, MessageType::KemResponse)?;
+
+    if private.0.len() < TRANSCRIPT_SIZE {
+        return Err(Error::Truncated);
+    }
+    let (request_hash, dk) = private.0.split_at(TRANSCRIPT_SIZE);
+
+    let level = level_of_decapsulation_key(dk)?;
+    if frame.parameters != level.id() {
+        return Err(Error::UnexpectedMessage);
+    }
+    check_size(&frame.payload, level.ciphertext_size())?;
+
+    let mut key1 = decapsulate(dk.to_vec(), frame.payload)?;
+
+    let key = derive(&[&key1], request_hash, &transcript_hash(&response), SHARED_SECRET_SIZE);
+    wipe(&mut key1);
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn seeds() -> ([u8; 32], [u8; 32]) {
+        (core::array::from_fn(|i| i as u8), core::array::from_fn(|i| 32 + i as u8))
+    }
+
+    #[test]
+    fn compress_matches_division() {
+        for d in 1..=11 {
+            for x in 0..Q {
+                assert_eq!(compress(x, d), (((x << (d + 1)) + Q) / (2 * Q)) & ((1 << d) - 1), "compress({}, {})", x, d);
+            }
+        }
+    }
+
+    #[test]
+    fn known_answer_keygen() {
+        let (d, z) = seeds();
+
+        for (level, expected) in [
+            (Level::MlKem512, "82f101ff648063b376e2bb6c5b7455f655a50c2feadade150efa0e0e6f365aea"),
+            (Level::MlKem768, "a24e16d8f8f9383a95b77050f4d9fd2f5733eec1d63ef3c23ebf9918173669a7"),
+            (Level::MlKem1024, "61349e5c131a7e116a0463861d7d18663c5627c38c7147ddaadfd48acd7a4535")
+        ] {
+            let (dk, ek) = keygen_internal(level, &d, &z);
+
+            assert_eq!(dk.len(), level.decapsulation_key_size());
+            assert_eq!(ek.len(), level.encapsulation_key_size());
+            assert_eq!(h(&ek), hex(expected));
+        }
+    }
+
+    #[test]
+    fn known_answer_decapsulate() {
+        let (d, z) = seeds();
+        let (dk, _) = keygen_internal(Level::MlKem512, &d, &z);
+
+        let ciphertext = hex(&[
+        "f72aa9b7cec5df0551b2c1b0c76ba60176b6e2edf43118e566f3f41e6823dd0154934e3f07e49a97262a10df49f9db64",
+        "e83eaf0c6f361cf956f296ab25962a00d14bb0439ca3d3e97a163ad65c68fe82459aa5d2473fc15ba5215e8366cd5724",
+        "2493eaad80a675048efb7eb7fcde9e95eca155f52f742290fca16c80c79d59fd061f47ad877c22c3e6fc4ae253ad2cb4",
+        "13d870175a57c20f443a978529201898f10d1d30ecd0097c19295fd03f19b6d6417e2d4ae11d17572359249569f3edb8",
+        "f62e77fdc49d5f4dcf9efd2ad0b1e34fe91e94607c7c72eedf9b578eb4f05970c15fe095a2b0a0a2a949c98238aed42a",
+        "f30b1bf94f614f7890f59e8c743baf657bd8dada27c7e623ab2324248e9574c93ebc2e4c8801e05af37a222f2efbcb22",
+        "096797fb7027c6aa6c5e60c20245dde337da07abb060f7567f89f714d3dbe14085b8aecbdde402d5ea11af62e209aa56",
+        "674511c962679a1ed16237343267f1f51aaa108108194f108535fea216f63cbb2ebf3046024f558cf13fca01ccfe332b",
+        "a28e7e40fc1dba52d9e0f06d4349c7ac2342fbc415210113874640c267e2b1b9a7d6b480b28455580ad2239f676d3a24",
+        "9315ca92ee797c9d1f0b27ba27b3360e98629d6a3fd0d5c19e10214e4024981acaed43059e0891fb71f427c7023af79c",
+        "a2ca3c0badd07809ab3ef87adb5554314de4033daa98f105ba2b0fcc236049b59958128197928562f4853b874859c090",
+        "7d96d2ab93e8f33d35222c1d2e2277e4e82d68c19cd7f5cd42d59efc3ebb1763c14469a717b0d19143a3444e0a178efc",
+        "e2c5005fc219537eaac8294d26aa8cc79276e0211c64c81e3770a899fff5be387ac4853dfaeb95c65895d7ff497b9bf2",
+        "d89b148abbe749a2dc5c5f74489f2475e6288f3df4cc802929b3214f08834af048752b084c1b955533d0846e33a672ae",
+        "bd7a714a9681718e4a07b0b39691cd9bd3716362db246f4f9ebb3883d96fbbf3b7cd591d42affd95df07e7a8c857c80c",
+        "32b891120d0a544a382ca50b4a33d2f1956a24567ad21218a9847c00b00c01c6f31649657a353a770be0f5066ab8a81e",
+        ].concat());
+
+        assert_eq!(
+            decapsulate(dk, ciphertext),
+            Ok(hex("6a9b42ed6e13d5a00d6cb90f01b66238bd6293e31dca7f35f57a16417524d0f6"))
+        );
+    }
+
+    #[test]
+    fn key_encapsulation() {
+        for level in Level::ALL {
+            for _ in 0..10 {
+                let (dk, ek) = keygen(level);
+                let (key2, ciphertext) = encapsulate(ek).unwrap();
+
+                assert_eq!(ciphertext.len(), level.ciphertext_size());
+                assert_eq!(decapsulate(dk, ciphertext), Ok(key2));
+            }
+        }
+    }
+
+    #[test]
+    fn implicit_rejection() {
+        let (d, z) = seeds();
+        let (dk, ek) = keygen_internal(Level::MlKem768, &d, &z);
+        let (key2, mut ciphertext) = encapsulate(ek).unwrap();
+
+        ciphertext[0] ^= 1;
+
+        let mut rejection = z.to_vec();
+        rejection.extend(&ciphertext);
+
+        let key1 = decapsulate(dk, ciphertext).unwrap();
+        assert_ne!(key1, key2);
+        assert_eq!(key1, j(&rejection));
+    }
+
+    #[test]
+    fn framed_exchange() {
+        for level in Level::ALL {
+            let (private1, public1) = request(level);
+            assert_eq!(Frame::decode(&public1).map(|frame| frame.parameters), Ok(level.id()));
+
+            let (key2, public2) = respond(public1).unwrap();
+            assert_eq!(Frame::decode(&public2).map(|frame| frame.payload.len()), Ok(level.ciphertext_size()));
+
+            assert_eq!(finalise(private1, public2), Ok(key2));
+        }
+    }
+
+    #[test]
+    fn malformed_frames() {
+        let (private1, public1) = request(Level::MlKem512);
+        let frame = Frame::decode(&public1).unwrap();
+
+        let reframe = |message_type, parameters, payload| Frame { message_type, parameters, payload }.encode();
+
+        assert_eq!(respond(public1[..20].to_vec()), Err(Error::Truncated));
+        assert_eq!(respond(reframe(MessageType::Request, 512, frame.payload.clone())), Err(Error::UnexpectedMessage));
+        assert_eq!(respond(reframe(MessageType::KemRequest, 513, frame.payload.clone())), Err(Error::UnsupportedParameters));
+        assert_eq!(respond(reframe(MessageType::KemRequest, 768, frame.payload.clone())), Err(Error::Truncated));
+        assert_eq!(respond(reframe(MessageType::KemRequest, 512, [frame.payload.clone(), vec![0]].concat())), Err(Error::TrailingData));
+
+        let (_, public2) = respond(public1).unwrap();
+        let ciphertext = Frame::decode(&public2).unwrap().payload;
+        let copy = || PrivateState::from_bytes(private1.as_bytes().to_vec());
+
+        assert_eq!(finalise(copy(), reframe(MessageType::KemResponse, 768, ciphertext.clone())), Err(Error::UnexpectedMessage));
+        assert_eq!(finalise(copy(), reframe(MessageType::KemResponse, 512, ciphertext[1..].to_vec())), Err(Error::Truncated));
+        assert_eq!(finalise(PrivateState::from_bytes(private1.as_bytes()[..40].to_vec()), public2.clone()), Err(Error::InvalidLength));
+        assert_eq!(finalise(copy(), public2).map(|key| key.len()), Ok(SHARED_SECRET_SIZE));
+    }
+
+    #[test]
+    fn malformed_keys() {
+        let (dk, ek) = keygen(Level::MlKem512);
+        let (_, ciphertext) = encapsulate(ek.clone()).unwrap();
+
+        assert_eq!(encapsulate(ek[1..].to_vec()), Err(Error::InvalidLength));
+        assert_eq!(decapsulate(dk[1..].to_vec(), ciphertext.clone()), Err(Error::InvalidLength));
+        assert_eq!(decapsulate(dk.clone(), ciphertext[1..].to_vec()), Err(Error::InvalidLength));
+
+        let mut bad = ek.clone();
+        bad[0] = 0xff;
+        bad[1] |= 0x0f;
+        assert_eq!(encapsulate(bad), Err(Error::CoefficientOutOfRange));
+
+        let mut bad = dk.clone();
+        bad[768 * 2 + 40] ^= 1;
+        assert_eq!(decapsulate(bad, ciphertext), Err(Error::InvalidKey));
+    }
+}
@@ -1,29 +1,104 @@
 #![allow(dead_code)]
 use crate::ring::NegacyclicRing;
 use crate::rlwe::*;
+use crate::params::ParameterSet;
+use crate::wire::{CUSTOM_PARAMETERS, Frame, MessageType};
+use crate::secret::wipe;
+use crate::rand::Rand;
+use ::rand::CryptoRng;
+use std::fmt;
+
+/// Errors returned by the key exchange
+pub mod error;
+pub use crate::error::Error;
 
 /// Contains representation of negacyclic polynomial rings, and utility functions
 pub mod ring;
 
+/// Precomputed tables for the number theoretic transform
+pub mod ntt;
+
+/// Polynomials bound to a ring, in coefficient space or ntt-space
+pub mod poly;
+
+/// Barrett and Montgomery modular reduction
+pub mod reduce;
+
 /// An implementation of ChaCha20
 pub mod chacha;
 
+/// An implementation of the Poly1305 one-time authenticator
+pub mod poly1305;
+
+/// ChaCha20-Poly1305 authenticated encryption
+pub mod aead;
+
+/// X25519 elliptic curve diffie hellman
+pub mod ecdh;
+
 /// Random
 pub mod rand;
 
+/// Wiping of secrets from memory
+pub mod secret;
+
+/// Constant time discrete Gaussian sampling
+pub mod gaussian;
+
+/// Statistical timing leakage tests, in the style of dudect
+#[cfg(test)]
+mod timing;
+
+/// An implementation of SHA3 and SHAKE
+pub mod sha3;
+
 /// Ring learning with errors key exchange
 pub mod rlwe;
 
+/// Key encapsulation mechanisms
+pub mod kem;
+
+/// The framed wire format of handshake messages
+pub mod wire;
+
+/// Named parameter sets, and validation of custom ones
+pub mod params;
+
+/// Derivation of shared secrets from the key exchange
+pub mod kdf;
+pub use crate::kdf::SharedSecret;
+use crate::kdf::{SHARED_SECRET_SIZE, derive, transcript_hash};
+
 /// The parameters for the ring learning with errors key exchange
 pub enum Security {
+    /// A 512 coefficient ring with cheap binomial noise and smaller messages, for constrained devices
+    Light,
     /// Equivelent to 128-bit RSA
     Medium,
-    /// You must make sure that the exponent and modulus form a negacyclic ring
-    /// with a 2n-th root of unity
+    /// A 1024 coefficient ring, for long term secrets
+    High,
+    /// A custom exponent and modulus, which are validated before use
     Custom(u32, i32)
 }
 
 impl Security {
+    /// Returns the parameter set of the security preset
+    ///
+    ///```
+    ///# use partav2::params::*;
+    ///# use partav2::*;
+    /// assert_eq!(Security::Medium.parameters(), MEDIUM);
+    /// assert_eq!(Security::Custom(9, 25601).parameters(), ParameterSet::custom(9, 25601));
+    ///```
+    pub fn parameters(&self) -> ParameterSet {
+        match self {
+            Security::Light => params::LIGHT,
+            Security::Medium => params::MEDIUM,
+            Security::High => params::HIGH,
+            Security::Custom(pow2, mod2) => ParameterSet::custom(*pow2, *mod2)
+        }
+    }
+
     /// Returns the ring from the security preset
     ///
     ///```
@@ -32,16 +107,177 @@ impl Security {
     /// assert_eq!(Security::Medium.ring(), NegacyclicRing::new(9, 25601));
     ///```
     pub fn ring(&self) -> NegacyclicRing {
-        NegacyclicRing::new(
-            match self {
-                Security::Medium => 9,
-                Security::Custom(pow2, _mod2) => *pow2
-            },
-            match self {
-                Security::Medium => 25601,
-                Security::Custom(_pow2, mod2) => *mod2
-            }
-        )
+        self.parameters().ring()
+    }
+
+    /// Returns the distribution of the secret and error polynomials from the security preset
+    ///
+    ///```
+    ///# use partav2::rlwe::*;
+    ///# use partav2::*;
+    /// assert_eq!(Security::Medium.noise(), Noise::Gaussian);
+    ///```
+    pub fn noise(&self) -> Noise {
+        self.parameters().noise
+    }
+
+    /// Returns the parameter set ID of the security preset on the wire
+    ///
+    ///```
+    ///# use partav2::*;
+    ///# use partav2::wire::*;
+    /// assert_eq!(Security::Medium.id(), 1);
+    /// assert_eq!(Security::Custom(9, 25601).id(), CUSTOM_PARAMETERS);
+    ///```
+    pub fn id(&self) -> u16 {
+        self.parameters().id
+    }
+}
+
+/// The size of the ring header (modulus, then exponent) at the start of a custom payload
+const HEADER_SIZE: usize = 8;
+
+/// The largest exponent a ring header may carry, as n must fit in an i32
+const MAX_HEADER_EXPONENT: u32 = 30;
+
+/// Checks that a parameter set can be used for the key exchange
+///
+/// A ring that no header could sensibly carry is a bad header, any other unusable set is unsupported.
+fn supported(parameters: &ParameterSet) -> Result<(), Error> {
+    if parameters.modulus < 2 || parameters.exponent > MAX_HEADER_EXPONENT {
+        return Err(Error::BadHeader);
+    }
+
+    parameters.validate()
+}
+
+/// Reads the parameters of a received message, taking the ring header from the start of
+/// the payload for a custom parameter set
+fn read_parameters(id: u16, payload: &mut Vec<u8>) -> Result<ParameterSet, Error> {
+    if id != CUSTOM_PARAMETERS {
+        return ParameterSet::from_id(id).ok_or(Error::UnsupportedParameters);
+    }
+
+    if payload.len() < HEADER_SIZE {
+        return Err(Error::Truncated);
+    }
+
+    let parameters = ParameterSet::custom(u32::from_le_bytes(payload[4..8].try_into().unwrap()), i32::from_le_bytes(payload[0..4].try_into().unwrap()));
+    payload.drain(0..HEADER_SIZE);
+
+    supported(&parameters)?;
+
+    Ok(parameters)
+}
+
+/// Frames a handshake message, starting the payload with the ring header for a custom parameter set
+fn write_message(message_type: MessageType, parameters: &ParameterSet, payload: Vec<u8>) -> Vec<u8> {
+    let mut body = vec![];
+    if parameters.id == CUSTOM_PARAMETERS {
+        body.extend(parameters.modulus.to_le_bytes());
+        body.extend(parameters.exponent.to_le_bytes());
+    }
+    body.extend(payload);
+
+    Frame {
+        message_type,
+        parameters: parameters.id,
+        payload: body
+    }.encode()
+}
+
+/// Reads a framed handshake message of the expected type, returning its parameters and payload
+fn read_message(message: &[u8], message_type: MessageType) -> Result<(ParameterSet, Vec<u8>), Error> {
+    let frame = Frame::decode_expecting(message, message_type)?;
+
+    let mut payload = frame.payload;
+    let parameters = read_parameters(frame.parameters, &mut payload)?;
+
+    Ok((parameters, payload))
+}
+
+/// Checks that a payload has the expected size
+fn check_size(payload: &[u8], expected: usize) -> Result<(), Error> {
+    if payload.len() < expected {
+        return Err(Error::Truncated);
+    }
+    if payload.len() > expected {
+        return Err(Error::TrailingData);
+    }
+
+    Ok(())
+}
+
+/// Packs a polynomial of bits into bytes, least significant bit first
+fn pack_bits(bits: Vec<i32>) -> Vec<u8> {
+    let mut key = vec![];
+
+    for i in 0..(bits.len() / 8) {
+        let mut val: u8 = 0;
+        for j in 0..8 {
+            val |= (bits[i * 8 + j] << j) as u8;
+        }
+        key.push(val);
+    }
+
+    key
+}
+
+/// The size of the transcript hash at the start of the private message
+const TRANSCRIPT_SIZE: usize = 32;
+
+/// The private state of a request, kept until its response arrives.
+///
+/// It holds the secret keys of the request, so it is wiped when dropped and is not printed by `Debug`.
+/// It can be stored with `as_bytes` and restored with `from_bytes`, but any copy made that way is the
+/// caller's to wipe.
+///
+///```
+/// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let stored = private1.as_bytes().to_vec();
+///
+/// let (key2, public2) = partav2::respond(public1).unwrap();
+/// assert_eq!(partav2::finalise(partav2::PrivateState::from_bytes(stored), public2).unwrap(), key2);
+/// assert_eq!(format!("{:?}", private1), "PrivateState");
+///```
+pub struct PrivateState(Vec<u8>);
+
+impl PrivateState {
+    /// A private state from bytes previously taken from `as_bytes`
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// The bytes of the private state
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// A private state starting with the transcript hash of the request, then the secret parts, each of
+    /// which is wiped once it has been copied
+    fn new(request: &[u8], parts: &mut [&mut [u8]]) -> Self {
+        // Reserved up front, so the buffer is never reallocated leaving copies behind
+        let mut private = Vec::with_capacity(TRANSCRIPT_SIZE + parts.iter().map(|part| part.len()).sum::<usize>());
+        private.extend(transcript_hash(request));
+
+        for part in parts.iter_mut() {
+            private.extend_from_slice(part);
+            wipe(part);
+        }
+
+        Self(private)
+    }
+}
+
+impl Drop for PrivateState {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+impl fmt::Debug for PrivateState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PrivateState")
     }
 }
 
@@ -49,85 +285,709 @@ impl Security {
 ///
 /// request(security) -> (private, request)
 ///
+/// Fails if the security level does not describe a usable ring.
+///
+///```
+/// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+///```
+pub fn request(level: Security) -> Result<(PrivateState, Vec<u8>), Error> {
+    request_with_rng(level, &mut Rand::new())
+}
+
+/// Generates a new request keypair for ring learning with errors, from the given generator
+///
+/// request_with_rng(security, rng) -> (private, request)
+///
 ///```
-/// let (private1, public1) = partav2::request(partav2::Security::Medium);
+///# use partav2::rand::Rand;
+/// let (private1, public1) = partav2::request_with_rng(partav2::Security::Medium, &mut Rand::from_seed([7; 32])).unwrap();
+/// let (private2, public2) = partav2::request_with_rng(partav2::Security::Medium, &mut Rand::from_seed([7; 32])).unwrap();
+///
+/// assert_eq!(public1, public2);
 ///```
-pub fn request(level: Security) -> (Vec<u8>, Vec<u8>) {
-    let ring = level.ring();
-    let (private1, public1) = RLWE::generate(ring);
+pub fn request_with_rng(level: Security, rng: &mut impl CryptoRng) -> Result<(PrivateState, Vec<u8>), Error> {
+    let parameters = level.parameters();
+    supported(&parameters)?;
 
-    let mut public = vec![];
-    public.extend(ring.modulus.to_le_bytes());
-    public.extend(ring.exponent.to_le_bytes());
-    public.extend(public1.to_bytes());
+    let (mut private1, payload) = generate_rlwe(&parameters, rng);
+    let public = write_message(MessageType::Request, &parameters, payload);
 
-    (private1.to_bytes(), public)
+    // The request is needed again to bind the secret to the transcript
+    let private = PrivateState::new(&public, &mut [&mut private1]);
+
+    Ok((private, public))
 }
 
 /// Generates a response keypair for ring learning with errors
 ///
 /// respond(request) -> (key, response)
 ///
+/// The key is a 32 byte secret, bound to both the request and the response.
+///
+/// Fails if the request is malformed.
+///
 ///```
-///# let (private1, public1) = partav2::request(partav2::Security::Medium);
-/// let (key2, public2) = partav2::respond(public1);
+///# let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond(public1).unwrap();
+/// assert_eq!(key2.len(), 32);
 ///```
-pub fn respond(request: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
-    let ring = NegacyclicRing::new(u32::from_le_bytes(request[4..8].try_into().unwrap()), i32::from_le_bytes(request[0..4].try_into().unwrap()));
-    let mut splice = request;
-    splice.drain(0..8);
+pub fn respond(request: Vec<u8>) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_with_length(request, SHARED_SECRET_SIZE)
+}
 
-    let public = PublicKeypair::from_bytes(splice);
+/// Generates a response keypair for ring learning with errors, with a key of len bytes
+///
+/// respond_with_length(request, len) -> (key, response)
+///
+///```
+///# let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_with_length(public1, 64).unwrap();
+/// assert_eq!(key2.len(), 64);
+///```
+pub fn respond_with_length(request: Vec<u8>, len: usize) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_with_length_and_rng(request, len, &mut Rand::new())
+}
 
-    let (key1, response) = RLWE::respond(ring, public);
+/// Generates a response keypair for ring learning with errors, from the given generator
+///
+/// respond_with_rng(request, rng) -> (key, response)
+///
+///```
+///# use partav2::rand::Rand;
+///# let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_with_rng(public1, &mut Rand::new()).unwrap();
+/// assert_eq!(key2.len(), 32);
+///```
+pub fn respond_with_rng(request: Vec<u8>, rng: &mut impl CryptoRng) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_with_length_and_rng(request, SHARED_SECRET_SIZE, rng)
+}
 
-    let mut key = vec![];
+/// Generates a response keypair for ring learning with errors, with a key of len bytes, from the
+/// given generator
+///
+/// respond_with_length_and_rng(request, len, rng) -> (key, response)
+///
+///```
+///# use partav2::rand::Rand;
+///# let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_with_length_and_rng(public1, 64, &mut Rand::new()).unwrap();
+/// assert_eq!(key2.len(), 64);
+///```
+pub fn respond_with_length_and_rng(request: Vec<u8>, len: usize, rng: &mut impl CryptoRng) -> Result<(SharedSecret, Vec<u8>), Error> {
+    let request_hash = transcript_hash(&request);
+    let (parameters, payload) = read_message(&request, MessageType::Request)?;
 
-    for i in 0..(key1.len() / 8) {
-        let mut val: u8 = 0;
-        for j in 0..8 {
-            val |= (key1[i * 8 + j] << j) as u8;
-        }
-        key.push(val);
-    }
+    let (key1, payload) = respond_rlwe(&parameters, payload, rng)?;
+    let public = write_message(MessageType::Response, &parameters, payload);
 
-    let mut public = vec![];
-    public.extend(ring.modulus.to_le_bytes());
-    public.extend(ring.exponent.to_le_bytes());
-    public.extend(response.to_bytes());
+    let key = derive(&[&key1], &request_hash, &transcript_hash(&public), len);
 
-    (key, public)
+    Ok((key, public))
 }
 
 /// Parses the response keypair
 ///
 /// finalise(rprivate, response) -> key
 ///
+/// Fails if the response is malformed, or does not match the private keypair.
+///
+///```
+/// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond(public1).unwrap();
+/// let key1 = partav2::finalise(private1, public2).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise(private: PrivateState, response: Vec<u8>) -> Result<SharedSecret, Error> {
+    finalise_with_length(private, response, SHARED_SECRET_SIZE)
+}
+
+/// Parses the response keypair, with a key of len bytes
+///
+/// finalise_with_length(rprivate, response, len) -> key
+///
 ///```
-/// let (private1, public1) = partav2::request(partav2::Security::Medium);
-/// let (key2, public2) = partav2::respond(public1);
-/// let key1 = partav2::finalise(private1, public2);
+/// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_with_length(public1, 64).unwrap();
+/// let key1 = partav2::finalise_with_length(private1, public2, 64).unwrap();
 ///
 /// assert_eq!(key1, key2);
 ///```
-pub fn finalise(private: Vec<u8>, request: Vec<u8>) -> Vec<u8> {
-    let ring = NegacyclicRing::new(u32::from_le_bytes(request[4..8].try_into().unwrap()), i32::from_le_bytes(request[0..4].try_into().unwrap()));
-    let mut splice = request;
-    splice.drain(0..8);
+pub fn finalise_with_length(private: PrivateState, response: Vec<u8>, len: usize) -> Result<SharedSecret, Error> {
+    finalise_with_length_and_rng(private, response, len, &mut Rand::new())
+}
 
-    let public = PublicKeypair::from_bytes(splice);
+/// Parses the response keypair, drawing its noise from the given generator
+///
+/// finalise_with_rng(rprivate, response, rng) -> key
+///
+///```
+///# use partav2::rand::Rand;
+/// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond(public1).unwrap();
+/// let key1 = partav2::finalise_with_rng(private1, public2, &mut Rand::new()).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise_with_rng(private: PrivateState, response: Vec<u8>, rng: &mut impl CryptoRng) -> Result<SharedSecret, Error> {
+    finalise_with_length_and_rng(private, response, SHARED_SECRET_SIZE, rng)
+}
 
-    let key1 = RLWE::parse(ring, PrivateKeypair::from_bytes(private), public);
+/// Parses the response keypair, with a key of len bytes, drawing its noise from the given generator
+///
+/// finalise_with_length_and_rng(rprivate, response, len, rng) -> key
+///
+///```
+///# use partav2::rand::Rand;
+/// let (private1, public1) = partav2::request(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_with_length(public1, 64).unwrap();
+/// let key1 = partav2::finalise_with_length_and_rng(private1, public2, 64, &mut Rand::new()).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise_with_length_and_rng(private: PrivateState, response: Vec<u8>, len: usize, rng: &mut impl CryptoRng) -> Result<SharedSecret, Error> {
+    let response_hash = transcript_hash(&response);
+    let (parameters, payload) = read_message(&response, MessageType::Response)?;
 
-    let mut key = vec![];
+    if private.0.len() < TRANSCRIPT_SIZE {
+        return Err(Error::Truncated);
+    }
+    let (request_hash, private1) = private.0.split_at(TRANSCRIPT_SIZE);
 
-    for i in 0..(key1.len() / 8) {
-        let mut val: u8 = 0;
-        for j in 0..8 {
-            val |= (key1[i * 8 + j] << j) as u8;
+    let key1 = finalise_rlwe(&parameters, private1, payload, rng)?;
+
+    Ok(derive(&[&key1], request_hash, &response_hash, len))
+}
+
+/// Generates a ring learning with errors keypair
+///
+/// generate_rlwe(parameters, rng) -> (private, payload)
+fn generate_rlwe(parameters: &ParameterSet, rng: &mut impl CryptoRng) -> (Vec<u8>, Vec<u8>) {
+    let ring = parameters.ring();
+    let (private, public) = RLWE::generate_with_rng(ring, parameters.noise, rng);
+
+    (private.to_bytes(&ring), public.to_bytes(&ring))
+}
+
+/// Responds to a ring learning with errors request payload
+///
+/// respond_rlwe(parameters, payload, rng) -> (raw key, payload)
+fn respond_rlwe(parameters: &ParameterSet, payload: Vec<u8>, rng: &mut impl CryptoRng) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let ring = parameters.ring();
+    check_size(&payload, PublicKeypair::size(&ring))?;
+    let public = PublicKeypair::from_bytes(&ring, payload)?;
+
+    let (key1, response) = RLWE::respond_with_rng(ring, parameters.noise, public, rng);
+
+    Ok((pack_bits(key1), response.to_bytes(&ring)))
+}
+
+/// Parses a ring learning with errors response payload with the private keypair
+///
+/// finalise_rlwe(parameters, private, payload, rng) -> raw key
+fn finalise_rlwe(parameters: &ParameterSet, private: &[u8], payload: Vec<u8>, rng: &mut impl CryptoRng) -> Result<Vec<u8>, Error> {
+    let ring = parameters.ring();
+    check_size(&payload, ResponseKeypair::size(&ring))?;
+    let public = ResponseKeypair::from_bytes(&ring, payload)?;
+    let private = PrivateKeypair::from_bytes(&ring, private.to_vec())?;
+
+    Ok(pack_bits(RLWE::parse_with_rng(ring, parameters.noise, private, public, rng)))
+}
+
+/// The size of an X25519 key, appended to the payload of every hybrid message
+const ECDH_SIZE: usize = 32;
+
+/// Splits the X25519 public key from the end of a hybrid payload
+fn split_ecdh(payload: Vec<u8>) -> Result<(Vec<u8>, [u8; ECDH_SIZE]), Error> {
+    if payload.len() < ECDH_SIZE {
+        return Err(Error::Truncated);
+    }
+
+    let mut payload = payload;
+    let ecdh = payload.split_off(payload.len() - ECDH_SIZE);
+
+    Ok((payload, ecdh.try_into().unwrap()))
+}
+
+/// Generates a new hybrid request keypair, for ring learning with errors and X25519
+///
+/// request_hybrid(security) -> (private, request)
+///
+/// The request carries a ring learning with errors request and an X25519 public key,
+/// so the exchange still takes only two messages.
+///
+///```
+/// let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+///```
+pub fn request_hybrid(level: Security) -> Result<(PrivateState, Vec<u8>), Error> {
+    request_hybrid_with_rng(level, &mut Rand::new())
+}
+
+/// Generates a new hybrid request keypair, for ring learning with errors and X25519, from the
+/// given generator
+///
+/// request_hybrid_with_rng(security, rng) -> (private, request)
+///
+///```
+///# use partav2::rand::Rand;
+/// let (private1, public1) = partav2::request_hybrid_with_rng(partav2::Security::Medium, &mut Rand::new()).unwrap();
+///```
+pub fn request_hybrid_with_rng(level: Security, rng: &mut impl CryptoRng) -> Result<(PrivateState, Vec<u8>), Error> {
+    let parameters = level.parameters();
+    supported(&parameters)?;
+
+    let (mut private1, mut payload) = generate_rlwe(&parameters, rng);
+    let (ecdh_private, ecdh_public) = ecdh::keypair_with_rng(rng);
+    payload.extend(ecdh_public);
+
+    let public = write_message(MessageType::HybridRequest, &parameters, payload);
+
+    let mut ecdh_private = *ecdh_private.as_bytes();
+    let private = PrivateState::new(&public, &mut [&mut ecdh_private, &mut private1]);
+
+    Ok((private, public))
+}
+
+/// Generates a hybrid response keypair, for ring learning with errors and X25519
+///
+/// respond_hybrid(request) -> (key, response)
+///
+/// The key is derived from both exchanges, so it stays secret unless both are broken.
+///
+/// Fails if the request is malformed, or its X25519 key is a low order point.
+///
+///```
+///# let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid(public1).unwrap();
+/// assert_eq!(key2.len(), 32);
+///```
+pub fn respond_hybrid(request: Vec<u8>) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_hybrid_with_length(request, SHARED_SECRET_SIZE)
+}
+
+/// Generates a hybrid response keypair, with a key of len bytes
+///
+/// respond_hybrid_with_length(request, len) -> (key, response)
+///
+///```
+///# let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid_with_length(public1, 64).unwrap();
+/// assert_eq!(key2.len(), 64);
+///```
+pub fn respond_hybrid_with_length(request: Vec<u8>, len: usize) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_hybrid_with_length_and_rng(request, len, &mut Rand::new())
+}
+
+/// Generates a hybrid response keypair, for ring learning with errors and X25519, from the given
+/// generator
+///
+/// respond_hybrid_with_rng(request, rng) -> (key, response)
+///
+///```
+///# use partav2::rand::Rand;
+///# let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid_with_rng(public1, &mut Rand::new()).unwrap();
+/// assert_eq!(key2.len(), 32);
+///```
+pub fn respond_hybrid_with_rng(request: Vec<u8>, rng: &mut impl CryptoRng) -> Result<(SharedSecret, Vec<u8>), Error> {
+    respond_hybrid_with_length_and_rng(request, SHARED_SECRET_SIZE, rng)
+}
+
+/// Generates a hybrid response keypair, with a key of len bytes, from the given generator
+///
+/// respond_hybrid_with_length_and_rng(request, len, rng) -> (key, response)
+///
+///```
+///# use partav2::rand::Rand;
+///# let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid_with_length_and_rng(public1, 64, &mut Rand::new()).unwrap();
+/// assert_eq!(key2.len(), 64);
+///```
+pub fn respond_hybrid_with_length_and_rng(request: Vec<u8>, len: usize, rng: &mut impl CryptoRng) -> Result<(SharedSecret, Vec<u8>), Error> {
+    let request_hash = transcript_hash(&request);
+    let (parameters, payload) = read_message(&request, MessageType::HybridRequest)?;
+    let (payload, ecdh_request) = split_ecdh(payload)?;
+
+    let (key1, mut payload) = respond_rlwe(&parameters, payload, rng)?;
+
+    let (ecdh_private, ecdh_public) = ecdh::keypair_with_rng(rng);
+    let key2 = ecdh::shared(&ecdh_private, ecdh_request)?;
+    payload.extend(ecdh_public);
+
+    let public = write_message(MessageType::HybridResponse, &parameters, payload);
+
+    let key = derive(&[&key1, &key2], &request_hash, &transcript_hash(&public), len);
+
+    Ok((key, public))
+}
+
+/// Parses the hybrid response keypair
+///
+/// finalise_hybrid(rprivate, response) -> key
+///
+/// Fails if the response is malformed, does not match the private keypair,
+/// or its X25519 key is a low order point.
+///
+///```
+/// let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid(public1).unwrap();
+/// let key1 = partav2::finalise_hybrid(private1, public2).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise_hybrid(private: PrivateState, response: Vec<u8>) -> Result<SharedSecret, Error> {
+    finalise_hybrid_with_length(private, response, SHARED_SECRET_SIZE)
+}
+
+/// Parses the hybrid response keypair, with a key of len bytes
+///
+/// finalise_hybrid_with_length(rprivate, response, len) -> key
+///
+///```
+/// let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid_with_length(public1, 64).unwrap();
+/// let key1 = partav2::finalise_hybrid_with_length(private1, public2, 64).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise_hybrid_with_length(private: PrivateState, response: Vec<u8>, len: usize) -> Result<SharedSecret, Error> {
+    finalise_hybrid_with_length_and_rng(private, response, len, &mut Rand::new())
+}
+
+/// Parses the hybrid response keypair, drawing its noise from the given generator
+///
+/// finalise_hybrid_with_rng(rprivate, response, rng) -> key
+///
+///```
+///# use partav2::rand::Rand;
+/// let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid(public1).unwrap();
+/// let key1 = partav2::finalise_hybrid_with_rng(private1, public2, &mut Rand::new()).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise_hybrid_with_rng(private: PrivateState, response: Vec<u8>, rng: &mut impl CryptoRng) -> Result<SharedSecret, Error> {
+    finalise_hybrid_with_length_and_rng(private, response, SHARED_SECRET_SIZE, rng)
+}
+
+/// Parses the hybrid response keypair, with a key of len bytes, drawing its noise from the given
+/// generator
+///
+/// finalise_hybrid_with_length_and_rng(rprivate, response, len, rng) -> key
+///
+///```
+///# use partav2::rand::Rand;
+/// let (private1, public1) = partav2::request_hybrid(partav2::Security::Medium).unwrap();
+/// let (key2, public2) = partav2::respond_hybrid_with_length(public1, 64).unwrap();
+/// let key1 = partav2::finalise_hybrid_with_length_and_rng(private1, public2, 64, &mut Rand::new()).unwrap();
+///
+/// assert_eq!(key1, key2);
+///```
+pub fn finalise_hybrid_with_length_and_rng(private: PrivateState, response: Vec<u8>, len: usize, rng: &mut impl CryptoRng) -> Result<SharedSecret, Error> {
+    let response_hash = transcript_hash(&response);
+    let (parameters, payload) = read_message(&response, MessageType::HybridResponse)?;
+    let (payload, ecdh_response) = split_ecdh(payload)?;
+
+    if private.0.len() < TRANSCRIPT_SIZE + ECDH_SIZE {
+        return Err(Error::Truncated);
+    }
+    let (request_hash, private1) = private.0.split_at(TRANSCRIPT_SIZE);
+    let (ecdh_private, private1) = private1.split_at(ECDH_SIZE);
+    let ecdh_private = ecdh::PrivateKey::from_bytes(ecdh_private.try_into().unwrap());
+
+    let key1 = finalise_rlwe(&parameters, private1, payload, rng)?;
+    let key2 = ecdh::shared(&ecdh_private, ecdh_response)?;
+
+    Ok(derive(&[&key1, &key2], request_hash, &response_hash, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::{FRAME_HEADER_SIZE, VERSION};
+
+    /// The first len bytes of a private state, which is deliberately not `Clone`
+    fn prefix(private: &PrivateState, len: usize) -> PrivateState {
+        PrivateState::from_bytes(private.as_bytes()[..len].to_vec())
+    }
+
+    fn copy(private: &PrivateState) -> PrivateState {
+        prefix(private, private.as_bytes().len())
+    }
+
+    #[test]
+    fn truncated_messages() {
+        let (private1, public1) = request(Security::Medium).unwrap();
+        let (_, public2) = respond(public1.clone()).unwrap();
+
+        for len in (0..public1.len()).step_by(7) {
+            assert!(respond(public1[..len].to_vec()).is_err());
         }
-        key.push(val);
+        for len in (0..public2.len()).step_by(7) {
+            assert!(finalise(copy(&private1), public2[..len].to_vec()).is_err());
+        }
+        for len in (0..private1.as_bytes().len()).step_by(7) {
+            assert!(finalise(prefix(&private1, len), public2.clone()).is_err());
+        }
+
+        assert_eq!(respond(public1[..4].to_vec()), Err(Error::Truncated));
+        assert_eq!(respond(public1[..public1.len() - 8].to_vec()), Err(Error::Truncated));
     }
 
-    key
+    #[test]
+    fn malformed_headers() {
+        let (_, public1) = request(Security::Medium).unwrap();
+
+        let mut bad = public1.clone();
+        bad[0] = b'X';
+        assert_eq!(respond(bad), Err(Error::BadHeader));
+
+        let mut bad = public1.clone();
+        bad[4] = VERSION + 1;
+        assert_eq!(respond(bad), Err(Error::UnsupportedVersion));
+
+        let mut bad = public1.clone();
+        bad[5] = MessageType::Response.to_byte();
+        assert_eq!(respond(bad), Err(Error::UnexpectedMessage));
+
+        let mut bad = public1.clone();
+        bad[6..8].copy_from_slice(&900u16.to_le_bytes());
+        assert_eq!(respond(bad), Err(Error::UnsupportedParameters));
+
+        // Bytes after the frame disagree with its length field
+        let mut bad = public1.clone();
+        bad.extend([0; 8]);
+        assert_eq!(respond(bad), Err(Error::BadHeader));
+
+        // A frame whose length field agrees, but whose payload is longer than the request
+        let mut frame = Frame::decode(&public1).unwrap();
+        frame.payload.extend([0; 8]);
+        assert_eq!(respond(frame.encode()), Err(Error::TrailingData));
+    }
+
+    #[test]
+    fn malformed_custom_headers() {
+        let (_, public1) = request(Security::Custom(9, 25601)).unwrap();
+        assert_eq!(public1[6..8], CUSTOM_PARAMETERS.to_le_bytes());
+
+        let ring = FRAME_HEADER_SIZE;
+
+        let mut bad = public1.clone();
+        bad[ring..ring + 4].copy_from_slice(&0i32.to_le_bytes());
+        assert_eq!(respond(bad), Err(Error::BadHeader));
+
+        let mut bad = public1.clone();
+        bad[ring + 4..ring + 8].copy_from_slice(&40u32.to_le_bytes());
+        assert_eq!(respond(bad), Err(Error::BadHeader));
+
+        let mut bad = public1.clone();
+        bad[ring..ring + 4].copy_from_slice(&25600i32.to_le_bytes());
+        assert_eq!(respond(bad), Err(Error::UnsupportedParameters));
+
+        // A valid ring, but with 2^26 coefficients, rejected before anything is allocated for it
+        let mut bad = public1.clone();
+        bad[ring..ring + 4].copy_from_slice(&2013265921i32.to_le_bytes());
+        bad[ring + 4..ring + 8].copy_from_slice(&26u32.to_le_bytes());
+        assert_eq!(respond(bad), Err(Error::UnsupportedParameters));
+
+        // A frame whose payload is too short for the ring header
+        let short = Frame {
+            message_type: MessageType::Request,
+            parameters: CUSTOM_PARAMETERS,
+            payload: vec![0; 4]
+        }.encode();
+        assert_eq!(respond(short), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn custom_parameters() {
+        let ring = Security::Custom(9, 40961).ring();
+
+        let (private1, public1) = request(Security::Custom(9, 40961)).unwrap();
+        assert_eq!(public1.len(), FRAME_HEADER_SIZE + HEADER_SIZE + PublicKeypair::size(&ring));
+
+        let (key2, public2) = respond(public1).unwrap();
+        assert_eq!(public2.len(), FRAME_HEADER_SIZE + HEADER_SIZE + ResponseKeypair::size(&ring));
+
+        assert_eq!(finalise(private1, public2).unwrap(), key2);
+    }
+
+    #[test]
+    fn preset_messages_carry_no_ring_header() {
+        let (_, public1) = request(Security::Medium).unwrap();
+
+        let frame = Frame::decode(&public1).unwrap();
+        assert_eq!(frame.message_type, MessageType::Request);
+        assert_eq!(frame.parameters, Security::Medium.id());
+        assert_eq!(frame.payload.len(), PublicKeypair::size(&Security::Medium.ring()));
+    }
+
+    #[test]
+    fn out_of_range_coefficients() {
+        let (private1, public1) = request(Security::Medium).unwrap();
+        let (_, public2) = respond(public1.clone()).unwrap();
+
+        // The first coefficient is 15 bits, so all ones is 32767
+        let mut bad = public1.clone();
+        bad[FRAME_HEADER_SIZE + SEED_SIZE] = 0xff;
+        bad[FRAME_HEADER_SIZE + SEED_SIZE + 1] |= 0x7f;
+        assert_eq!(respond(bad), Err(Error::CoefficientOutOfRange));
+
+        // After the 512 bit signal
+        let mut bad = public2.clone();
+        bad[FRAME_HEADER_SIZE + 64] = 0xff;
+        bad[FRAME_HEADER_SIZE + 65] |= 0x7f;
+        assert_eq!(finalise(private1, bad), Err(Error::CoefficientOutOfRange));
+    }
+
+    #[test]
+    fn shared_secret_length() {
+        for len in [16, 32, 64, 100] {
+            let (private1, public1) = request(Security::Medium).unwrap();
+            let (key2, public2) = respond_with_length(public1, len).unwrap();
+            let key1 = finalise_with_length(private1, public2, len).unwrap();
+
+            assert_eq!(key1.len(), len);
+            assert_eq!(key1, key2);
+        }
+    }
+
+    #[test]
+    fn shared_secret_bound_to_transcript() {
+        let (private1, public1) = request(Security::Medium).unwrap();
+        let (key2, public2) = respond(public1).unwrap();
+
+        let mut other = private1.as_bytes().to_vec();
+        other[0] ^= 1;
+        let other = PrivateState::from_bytes(other);
+
+        assert_eq!(finalise(private1, public2.clone()).unwrap(), key2);
+        assert_ne!(finalise(other, public2).unwrap(), key2);
+    }
+
+    #[test]
+    fn seeded_exchange_is_reproducible() {
+        let exchange = |hybrid: bool| {
+            let mut rng = Rand::from_seed([3; 32]);
+
+            if hybrid {
+                let (private1, public1) = request_hybrid_with_rng(Security::Medium, &mut rng).unwrap();
+                let (key2, public2) = respond_hybrid_with_rng(public1.clone(), &mut rng).unwrap();
+                let key1 = finalise_hybrid_with_rng(private1, public2.clone(), &mut rng).unwrap();
+
+                (public1, public2, key1, key2)
+            } else {
+                let (private1, public1) = request_with_rng(Security::Medium, &mut rng).unwrap();
+                let (key2, public2) = respond_with_rng(public1.clone(), &mut rng).unwrap();
+                let key1 = finalise_with_rng(private1, public2.clone(), &mut rng).unwrap();
+
+                (public1, public2, key1, key2)
+            }
+        };
+
+        for hybrid in [false, true] {
+            let (public1, public2, key1, key2) = exchange(hybrid);
+            assert_eq!(key1, key2);
+            assert_eq!(exchange(hybrid), (public1, public2, key1, key2));
+        }
+    }
+
+    /// Runs the exchange many times at a security level, and checks both sides always agree
+    fn agrees(level: fn() -> Security, runs: usize) {
+        for _ in 0..runs {
+            let (private1, public1) = request(level()).unwrap();
+            let (key2, public2) = respond(public1).unwrap();
+
+            assert_eq!(finalise(private1, public2).unwrap(), key2, "{}", level().parameters().name);
+        }
+    }
+
+    #[test]
+    fn light_agrees() {
+        agrees(|| Security::Light, 2000);
+    }
+
+    #[test]
+    fn medium_agrees() {
+        agrees(|| Security::Medium, 2000);
+    }
+
+    #[test]
+    fn high_agrees() {
+        agrees(|| Security::High, 2000);
+    }
+
+    #[test]
+    fn presets() {
+        for level in [Security::Light, Security::Medium, Security::High] {
+            let parameters = level.parameters();
+
+            assert_eq!(parameters.validate(), Ok(()));
+            assert_eq!(params::ParameterSet::from_id(level.id()), Some(parameters));
+
+            let (_, public1) = request(level).unwrap();
+            assert_eq!(Frame::decode(&public1).unwrap().parameters, parameters.id);
+        }
+
+        assert_eq!(Security::Light.ring(), NegacyclicRing::new(9, 12289));
+        assert_eq!(Security::Medium.ring(), NegacyclicRing::new(9, 25601));
+        assert_eq!(Security::High.ring(), NegacyclicRing::new(10, 12289));
+    }
+
+    #[test]
+    fn unsupported_security() {
+        assert_eq!(request(Security::Custom(9, 25600)).err(), Some(Error::UnsupportedParameters));
+        assert_eq!(request(Security::Custom(9, 0)).err(), Some(Error::BadHeader));
+        assert_eq!(request(Security::Custom(10, 2147473409)).err(), Some(Error::UnsupportedParameters));
+    }
+
+    #[test]
+    fn hybrid_exchange() {
+        let (private1, public1) = request_hybrid(Security::Medium).unwrap();
+        let (key2, public2) = respond_hybrid(public1.clone()).unwrap();
+
+        assert_eq!(finalise_hybrid(copy(&private1), public2.clone()).unwrap(), key2);
+
+        // Neither side of a hybrid exchange can be parsed as a plain exchange
+        assert_eq!(respond(public1), Err(Error::UnexpectedMessage));
+        assert_eq!(finalise(private1, public2), Err(Error::UnexpectedMessage));
+    }
+
+    #[test]
+    fn hybrid_truncated_messages() {
+        let (private1, public1) = request_hybrid(Security::Medium).unwrap();
+        let (_, public2) = respond_hybrid(public1.clone()).unwrap();
+
+        for len in (0..public1.len()).step_by(7) {
+            assert!(respond_hybrid(public1[..len].to_vec()).is_err());
+        }
+        for len in (0..public2.len()).step_by(7) {
+            assert!(finalise_hybrid(copy(&private1), public2[..len].to_vec()).is_err());
+        }
+        for len in (0..private1.as_bytes().len()).step_by(7) {
+            assert!(finalise_hybrid(prefix(&private1, len), public2.clone()).is_err());
+        }
+
+        assert_eq!(respond_hybrid(public1[..16].to_vec()), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn hybrid_depends_on_both_exchanges() {
+        let (private1, public1) = request_hybrid(Security::Medium).unwrap();
+        let (key2, public2) = respond_hybrid(public1).unwrap();
+
+        // A different X25519 private key
+        let mut other = private1.as_bytes().to_vec();
+        other[TRANSCRIPT_SIZE] ^= 0x10;
+        let other = PrivateState::from_bytes(other);
+        assert_ne!(finalise_hybrid(other, public2.clone()).unwrap(), key2);
+
+        // A low order X25519 public key
+        let mut bad = public2.clone();
+        let len = bad.len();
+        bad[len - ECDH_SIZE..].copy_from_slice(&[0; ECDH_SIZE]);
+        assert_eq!(finalise_hybrid(copy(&private1), bad), Err(Error::InvalidKey));
+
+        assert_eq!(finalise_hybrid_with_length(private1, public2, 64).unwrap().len(), 64);
+    }
 }
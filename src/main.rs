@@ -1,21 +1,49 @@
 use partav2::ring::*;
 use partav2::rlwe::*;
+use partav2::ntt::*;
+use partav2::reduce::*;
 
 use std::time::SystemTime;
 
 fn main() {
     const ITERATIONS: i32 = 1000;
-    let start = SystemTime::now();
+    let mut division = 0f32;
+
+    for reduction in [Reduction::Division, Reduction::Barrett, Reduction::Montgomery] {
+        let ring = NegacyclicRing::new(9, 25601).with_reduction(reduction);
+        let start = SystemTime::now();
+
+        for _ in 0..ITERATIONS {
+            let (private1, public1) = RLWE::generate(ring, Noise::Gaussian);
+            let (key2, public2) = RLWE::respond(ring, Noise::Gaussian, public1);
+            let key1 = RLWE::parse(ring, Noise::Gaussian, private1, public2);
+
+            assert_eq!(key1, key2);
+        }
+
+        let elapsed = start.elapsed().unwrap().as_micros() as f32 / 1000f32 / ITERATIONS as f32;
+        if reduction == Reduction::Division {
+            division = elapsed;
+        }
 
-    for _ in 0..ITERATIONS {
-        let ring = NegacyclicRing::new(9, 25601);
+        println!("{} iterations: {}ms/keyshare with {:?} reduction ({}x speedup)", ITERATIONS, (elapsed * 100f32).round() / 100f32, reduction, (division / elapsed * 100f32).round() / 100f32);
+    }
 
-        let (private1, public1) = RLWE::generate(ring);
-        let (key2, public2) = RLWE::respond(ring, public1);
-        let key1 = RLWE::parse(ring, private1, public2);
+    const NTT_ITERATIONS: i32 = 100;
+    let ring = NegacyclicRing::new(9, 25601);
+    let poly = ring.sample();
 
-        assert_eq!(key1, key2);
+    let start = SystemTime::now();
+    for _ in 0..NTT_ITERATIONS {
+        NttContext::new(ring).unwrap().ntt(poly.clone());
+    }
+    let uncached = start.elapsed().unwrap().as_micros() as f32 / NTT_ITERATIONS as f32;
+
+    let start = SystemTime::now();
+    for _ in 0..NTT_ITERATIONS {
+        ring.ntt(poly.clone()).unwrap();
     }
+    let cached = start.elapsed().unwrap().as_micros() as f32 / NTT_ITERATIONS as f32;
 
-    println!("{} iterations: {}ms/keyshare", ITERATIONS, (start.elapsed().unwrap().as_micros() as f32 / 1000f32 / ITERATIONS as f32 * 10f32).round() / 10f32);
+    println!("{} iterations: {}us/ntt uncached, {}us/ntt cached ({}x speedup)", NTT_ITERATIONS, uncached.round(), cached.round(), (uncached / cached).round());
 }
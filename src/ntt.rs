@@ -0,0 +1,275 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::params::REGISTRY;
+use crate::ring::NegacyclicRing;
+
+/// Precomputed tables for the number theoretic transform in a negacyclic ring.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NttContext {
+    pub ring: NegacyclicRing,
+
+    /// Powers of the primitive 2nth root of unity, in bit reversed order
+    pub roots: Vec<i32>,
+    /// Powers of the inverse primitive 2nth root of unity, in bit reversed order
+    pub inverse_roots: Vec<i32>,
+    /// The inverse of n with respect to the modulus
+    pub n_inverse: i32,
+
+    /// The bit reversal permutation of the indices
+    pub reversed: Vec<usize>,
+
+    /// The roots, inverse roots and inverse of n, in Montgomery form if the ring uses Montgomery reduction
+    twiddles: Vec<i32>,
+    inverse_twiddles: Vec<i32>,
+    n_inverse_twiddle: i32
+}
+
+impl NttContext {
+    /// Builds the tables for a ring, if it has a primitive 2nth root of unity.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::ntt::*;
+    /// let context = NttContext::new(NegacyclicRing::new(2, 7681)).unwrap();
+    /// assert_eq!(context.roots, vec![1, 3383, 1925, 6468]);
+    /// assert_eq!(context.n_inverse, 5761);
+    ///
+    /// assert_eq!(NttContext::new(NegacyclicRing::new(9, 25600)), None);
+    /// ```
+    pub fn new(ring: NegacyclicRing) -> Option<Self> {
+        let root = ring.primitive2nthunity()?;
+        let inverse_root = ring.inverse(root)?;
+        let n_inverse = ring.inverse(ring.size())?;
+
+        let k = ring.exponent as i32;
+        let n = ring.size() as usize;
+
+        let reversed: Vec<usize> = (0..n).map(|i| NegacyclicRing::bit_reverse(i as i32, k) as usize).collect();
+
+        let roots: Vec<i32> = reversed.iter().map(|&i| ring.power(root, i as i32)).collect();
+        let inverse_roots: Vec<i32> = reversed.iter().map(|&i| ring.power(inverse_root, i as i32)).collect();
+
+        let twiddle = |x: i32| ring.montgomery().map_or(x, |montgomery| montgomery.to_montgomery(x as u32) as i32);
+
+        Some(Self {
+            ring,
+            twiddles: roots.iter().map(|&x| twiddle(x)).collect(),
+            inverse_twiddles: inverse_roots.iter().map(|&x| twiddle(x)).collect(),
+            n_inverse_twiddle: twiddle(n_inverse),
+            roots,
+            inverse_roots,
+            n_inverse,
+            reversed
+        })
+    }
+
+    /// Multiplies x by a twiddle factor from the tables.
+    fn twiddle(&self, x: i32, twiddle: i32) -> i32 {
+        match self.ring.montgomery() {
+            Some(montgomery) => montgomery.mul(x as u32, twiddle as u32) as i32,
+            None => self.ring.mul_mod(x, twiddle)
+        }
+    }
+
+    /// Number theoretic transform using the precomputed tables.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::ntt::*;
+    /// let context = NttContext::new(NegacyclicRing::new(2, 7681)).unwrap();
+    /// assert_eq!(
+    ///     context.ntt(vec![1, 2, 3, 4]),
+    ///     vec![1467, 2807, 3471, 7621]
+    /// );
+    /// ```
+    pub fn ntt(&self, val: Vec<i32>) -> Vec<i32> {
+        let mut out: Vec<i32> = self.ring.pad(val).into_iter().map(|v| self.ring.reduce(v)).collect();
+        let n = out.len();
+
+        let mut t: usize = n;
+        let mut m: usize = 1;
+        while m < n {
+            t /= 2;
+
+            for i in 0..m {
+                let j1 = 2 * i * t;
+                let j2 = j1 + t;
+                let s = self.twiddles[m + i];
+
+                for j in j1..j2 {
+                    let u = out[j];
+                    let v = self.twiddle(out[j + t], s);
+                    out[j] = self.ring.add_mod(u, v);
+                    out[j + t] = self.ring.sub_mod(u, v);
+                }
+            }
+
+            m *= 2;
+        }
+
+        self.reversed.iter().map(|&i| out[i]).collect()
+    }
+
+    /// Inverse number theoretic transform using the precomputed tables.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::ntt::*;
+    /// let context = NttContext::new(NegacyclicRing::new(2, 7681)).unwrap();
+    /// assert_eq!(
+    ///     context.intt(vec![1467, 2807, 3471, 7621]),
+    ///     vec![1, 2, 3, 4]
+    /// );
+    /// ```
+    pub fn intt(&self, val: Vec<i32>) -> Vec<i32> {
+        let n = self.reversed.len();
+
+        let mut out: Vec<i32> = self.reversed.iter().map(|&i| self.ring.reduce(val[i])).collect();
+
+        let mut t: usize = 1;
+        let mut m: usize = n;
+        while m > 1 {
+            let mut j1 = 0;
+            let h = m / 2;
+
+            for i in 0..h {
+                let j2 = j1 + t;
+                let s = self.inverse_twiddles[h + i];
+
+                for j in j1..j2 {
+                    let u = out[j];
+                    let v = out[j + t];
+                    out[j] = self.ring.add_mod(u, v);
+                    out[j + t] = self.twiddle(self.ring.sub_mod(u, v), s);
+                }
+
+                j1 += t * 2;
+            }
+
+            t *= 2;
+            m /= 2;
+        }
+
+        out.into_iter().map(|v| self.twiddle(v, self.n_inverse_twiddle)).collect()
+    }
+
+    /// Multiplication of polynomials using the precomputed tables.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::ntt::*;
+    /// let context = NttContext::new(NegacyclicRing::new(3, 7681)).unwrap();
+    /// assert_eq!(
+    ///     context.mul(vec![1, 1], vec![0, 1]),
+    ///     vec![0, 1, 1, 0, 0, 0, 0, 0]
+    /// );
+    /// ```
+    pub fn mul(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
+        let antt = self.ntt(a);
+        let bntt = self.ntt(b);
+
+        self.intt(self.ring.mul_ntt(antt, bntt))
+    }
+}
+
+/// The most contexts of unregistered rings kept at once, which peers may choose with custom parameters
+pub const CACHE_SIZE: usize = 8;
+
+/// Contexts which have already been built, shared between all users of a ring
+struct Contexts {
+    /// The contexts of the registered parameter sets, which are kept forever
+    presets: HashMap<NegacyclicRing, Option<Arc<NttContext>>>,
+    /// The contexts of other rings, most recently used first
+    recent: VecDeque<(NegacyclicRing, Option<Arc<NttContext>>)>
+}
+
+static CONTEXTS: OnceLock<Mutex<Contexts>> = OnceLock::new();
+
+impl Contexts {
+    fn get(&mut self, ring: &NegacyclicRing) -> Option<Option<Arc<NttContext>>> {
+        if let Some(context) = self.presets.get(ring) {
+            return Some(context.clone());
+        }
+
+        let i = self.recent.iter().position(|(other, _)| other == ring)?;
+        let entry = self.recent.remove(i).unwrap();
+        self.recent.push_front(entry.clone());
+
+        Some(entry.1)
+    }
+
+    fn insert(&mut self, ring: NegacyclicRing, context: Option<Arc<NttContext>>) -> Option<Arc<NttContext>> {
+        // Another thread may have built it first
+        if let Some(context) = self.get(&ring) {
+            return context;
+        }
+
+        if REGISTRY.iter().any(|set| set.ring() == ring) {
+            self.presets.insert(ring, context.clone());
+        } else {
+            self.recent.push_front((ring, context.clone()));
+            self.recent.truncate(CACHE_SIZE);
+        }
+
+        context
+    }
+}
+
+impl NegacyclicRing {
+    /// Returns the shared NTT context of the ring, building it on first use.
+    ///
+    /// The contexts of the registered parameter sets are kept forever, and those of the last
+    /// [`CACHE_SIZE`] other rings used.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(9, 25601);
+    /// assert!(std::sync::Arc::ptr_eq(&ring.context().unwrap(), &ring.context().unwrap()));
+    ///
+    /// assert!(NegacyclicRing::new(9, 25600).context().is_none());
+    /// ```
+    pub fn context(&self) -> Option<Arc<NttContext>> {
+        let contexts = CONTEXTS.get_or_init(|| Mutex::new(Contexts {
+            presets: HashMap::new(),
+            recent: VecDeque::new()
+        }));
+
+        if let Some(context) = contexts.lock().unwrap().get(self) {
+            return context;
+        }
+
+        // Built without holding the lock, as finding the roots of unity is slow
+        let context = NttContext::new(*self).map(Arc::new);
+
+        contexts.lock().unwrap().insert(*self, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_is_bounded() {
+        let preset = REGISTRY[0].ring();
+        let kept = preset.context().unwrap();
+
+        // Rings which only differ by their modulus, each 1 mod 32
+        let rings: Vec<NegacyclicRing> = (1..).map(|k| 32 * k + 1).filter(|&q| crate::params::is_prime(q)).take(2 * CACHE_SIZE).map(|q| NegacyclicRing::new(4, q)).collect();
+        let first = rings[0].context().unwrap();
+
+        for ring in &rings {
+            ring.context().unwrap();
+        }
+
+        let contexts = CONTEXTS.get().unwrap().lock().unwrap();
+        assert!(contexts.recent.len() <= CACHE_SIZE);
+        assert!(contexts.recent.iter().all(|(ring, _)| !REGISTRY.iter().any(|set| set.ring() == *ring)));
+        drop(contexts);
+
+        // The preset is never evicted, but the least recently used ring is
+        assert!(Arc::ptr_eq(&preset.context().unwrap(), &kept));
+        assert!(!Arc::ptr_eq(&rings[0].context().unwrap(), &first));
+    }
+}
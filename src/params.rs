@@ -0,0 +1,343 @@
+use crate::error::Error;
+use crate::ring::NegacyclicRing;
+use crate::rlwe::Noise;
+use crate::wire::CUSTOM_PARAMETERS;
+
+/// The largest exponent of a usable ring, n = 2048, so a peer cannot make a handshake allocate and
+/// transform polynomials of any size it likes
+pub const MAX_EXPONENT: u32 = 11;
+
+/// The fewest coefficients a ring may have, as each gives one bit of the raw shared key
+pub const MIN_SIZE: i32 = 256;
+
+/// The largest estimated probability that the two sides of an exchange disagree on the key
+pub const MAX_FAILURE_PROBABILITY: f64 = 1.0 / (1u64 << 40) as f64;
+
+/// The least estimated classical security in bits, from [`ParameterSet::estimate_security`], that a usable
+/// parameter set may have
+pub const MIN_SECURITY: u32 = 100;
+
+/// The method used to reconcile the approximately equal keys of both sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconciliation {
+    /// One signal bit per coefficient, set when it is outside [q/4, 3q/4] (Ding, 2012)
+    Signal
+}
+
+/// A named set of parameters for the key exchange.
+///
+///```
+///# use partav2::params::*;
+/// let medium = ParameterSet::from_name("medium").unwrap();
+///
+/// assert_eq!(ParameterSet::from_id(medium.id), Some(medium));
+/// assert_eq!(medium.validate(), Ok(()));
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParameterSet {
+    /// The ID of the parameter set on the wire
+    pub id: u16,
+    pub name: &'static str,
+    /// The ring has 2 ^ exponent coefficients
+    pub exponent: u32,
+    pub modulus: i32,
+    pub noise: Noise,
+    pub reconciliation: Reconciliation,
+    /// The estimated classical security in bits, from [`ParameterSet::estimate_security`]
+    pub security: u32
+}
+
+/// n = 512, q = 12289, with binomial noise, for constrained devices.
+///
+/// These are the NewHope512 parameters (Alkim et al., NIST round 2 submission), which claim 112 bits of
+/// classical core-SVP security. Binomial noise is much cheaper to sample than Gaussian, and coefficients
+/// pack into 14 bits rather than the 15 of [`MEDIUM`].
+pub const LIGHT: ParameterSet = ParameterSet {
+    id: 2,
+    name: "light",
+    exponent: 9,
+    modulus: 12289,
+    noise: Noise::Binomial(8),
+    reconciliation: Reconciliation::Signal,
+    security: 112
+};
+
+/// n = 512, q = 25601, with Gaussian noise
+pub const MEDIUM: ParameterSet = ParameterSet {
+    id: 1,
+    name: "medium",
+    exponent: 9,
+    modulus: 25601,
+    noise: Noise::Gaussian,
+    reconciliation: Reconciliation::Signal,
+    security: 114
+};
+
+/// n = 1024, q = 12289 (as in NewHope), with binomial noise
+pub const HIGH: ParameterSet = ParameterSet {
+    id: 3,
+    name: "high",
+    exponent: 10,
+    modulus: 12289,
+    noise: Noise::Binomial(8),
+    reconciliation: Reconciliation::Signal,
+    security: 259
+};
+
+/// The named parameter sets, in order of ID
+pub const REGISTRY: [ParameterSet; 3] = [MEDIUM, LIGHT, HIGH];
+
+impl ParameterSet {
+    /// An unnamed parameter set for a custom ring, with Gaussian noise.
+    ///
+    /// It is not checked, so it must be validated before use.
+    pub fn custom(exponent: u32, modulus: i32) -> Self {
+        let mut set = Self {
+            id: CUSTOM_PARAMETERS,
+            name: "custom",
+            exponent,
+            modulus,
+            noise: Noise::Gaussian,
+            reconciliation: Reconciliation::Signal,
+            security: 0
+        };
+
+        if modulus >= 2 && exponent <= MAX_EXPONENT {
+            set.security = set.estimate_security();
+        }
+
+        set
+    }
+
+    /// The named parameter set with the given wire ID
+    pub fn from_id(id: u16) -> Option<Self> {
+        REGISTRY.into_iter().find(|set| set.id == id)
+    }
+
+    /// The named parameter set with the given name
+    pub fn from_name(name: &str) -> Option<Self> {
+        REGISTRY.into_iter().find(|set| set.name == name)
+    }
+
+    /// The ring of the parameter set
+    pub fn ring(&self) -> NegacyclicRing {
+        NegacyclicRing::new(self.exponent, self.modulus)
+    }
+
+    /// Estimates the probability that the two sides of an exchange disagree on any bit of the key.
+    ///
+    /// The keys differ by 2 (e s' - e' s + e'' - e'''), which is approximated as Gaussian, and a bit
+    /// disagrees when a coefficient of the difference is beyond q / 4.
+    ///
+    ///```
+    ///# use partav2::params::*;
+    /// assert!(MEDIUM.failure_probability() < 1e-15);
+    /// assert!(ParameterSet::custom(8, 7681).failure_probability() > 1e-3);
+    ///```
+    pub fn failure_probability(&self) -> f64 {
+        let n = 2f64.powi(self.exponent as i32);
+        let variance = self.noise.variance();
+
+        let deviation = (4.0 * (2.0 * n * variance * variance + 2.0 * variance)).sqrt();
+
+        (n * erfc(self.modulus as f64 / 4.0 / (deviation * std::f64::consts::SQRT_2))).min(1.0)
+    }
+
+    /// Estimates the classical security of the parameter set in bits.
+    ///
+    /// This is the core-SVP cost, 0.292 b, of the primal attack with the smallest BKZ block size b that
+    /// recovers the secret from up to 2n samples, as estimated for NewHope (Alkim, Ducas, Pöppelmann and
+    /// Schwabe, 2016). It ignores the dual attack and the structure of the ring, so it is a floor for
+    /// rejecting weak sets rather than a proof of strength.
+    ///
+    ///```
+    ///# use partav2::params::*;
+    /// assert_eq!(HIGH.estimate_security(), 259);
+    /// assert_eq!(ParameterSet::custom(10, 2147473409).estimate_security(), 90); // q is far too large for the noise
+    ///```
+    pub fn estimate_security(&self) -> u32 {
+        let n = 2f64.powi(self.exponent as i32);
+        let (log_q, log_sigma) = ((self.modulus as f64).ln(), self.noise.variance().sqrt().ln());
+
+        let block = (50..=3 * n as usize + 1).find(|&b| {
+            let b = b as f64;
+            let log_delta = (((std::f64::consts::PI * b).ln() / b) + (b / (2.0 * std::f64::consts::PI * std::f64::consts::E)).ln()) / (2.0 * (b - 1.0));
+
+            // The best number of samples m maximises (2b - d - 1) log delta + (m / d) log q, for d = m + n + 1
+            let best = (log_q * (n + 1.0) / log_delta).sqrt() - n - 1.0;
+
+            [best.floor(), best.floor() + 1.0].into_iter().any(|m| {
+                let m = m.clamp((b - n - 1.0).max(1.0), 2.0 * n);
+                let d = m + n + 1.0;
+
+                log_sigma + 0.5 * b.ln() <= (2.0 * b - d - 1.0) * log_delta + m / d * log_q
+            })
+        }).unwrap_or(3 * n as usize + 1);
+
+        (0.292 * block as f64) as u32
+    }
+
+    /// Checks that the parameter set can be used for the key exchange.
+    ///
+    /// Fails if the ring is out of range or has more than 2 ^ [`MAX_EXPONENT`] coefficients, q is not
+    /// a prime with q = 1 mod 2n, the keys are too likely to disagree, or the estimated security is below
+    /// [`MIN_SECURITY`]. Products of coefficients are widened before they are reduced, so any positive
+    /// i32 modulus is in range.
+    ///
+    ///```
+    ///# use partav2::params::*;
+    ///# use partav2::error::Error;
+    /// assert_eq!(ParameterSet::custom(9, 25601).validate(), Ok(()));
+    /// assert_eq!(ParameterSet::custom(9, 25607).validate(), Err(Error::UnsupportedParameters)); // 25607 = 29 * 883
+    /// assert_eq!(ParameterSet::custom(9, 7681).validate(), Err(Error::UnsupportedParameters)); // 7680 is not a multiple of 1024
+    /// assert_eq!(ParameterSet::custom(11, 2147389441).validate(), Ok(()));
+    /// assert_eq!(ParameterSet::custom(10, 2147473409).validate(), Err(Error::UnsupportedParameters)); // too weak
+    /// assert_eq!(ParameterSet::custom(8, 7681).validate(), Err(Error::UnsupportedParameters)); // fails too often
+    /// assert_eq!(ParameterSet::custom(26, 2013265921).validate(), Err(Error::UnsupportedParameters)); // n = 2^26 is far too large
+    ///```
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.modulus < 2 || self.exponent > MAX_EXPONENT {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        let ring = self.ring();
+
+        if ring.size() < MIN_SIZE {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        if !is_prime(self.modulus) || (self.modulus - 1) as i64 % (2 * ring.size() as i64) != 0 {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        if let Noise::Binomial(eta) = self.noise && !(1..=16).contains(&eta) {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        if self.failure_probability() > MAX_FAILURE_PROBABILITY {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        if self.estimate_security() < MIN_SECURITY {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks whether a number is prime, by trial division.
+///
+///```
+///# use partav2::params::*;
+/// assert!(is_prime(25601));
+/// assert!(!is_prime(25607));
+/// assert!(!is_prime(1));
+///```
+pub fn is_prime(x: i32) -> bool {
+    x >= 2 && (2..).take_while(|d| *d <= x / d).all(|d| x % d != 0)
+}
+
+/// The complementary error function, with a relative error below 1.2e-7 (Numerical Recipes, erfcc).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+
+    let coefficients = [-1.26551223, 1.00002368, 0.37409196, 0.09678418, -0.18628806, 0.27886807, -1.13520398, 1.48851587, -0.82215223, 0.17087277];
+    let polynomial = coefficients.iter().rev().fold(0.0, |acc, c| acc * t + c);
+
+    let result = t * (-z * z + polynomial).exp();
+
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        for (i, set) in REGISTRY.iter().enumerate() {
+            assert_eq!(set.validate(), Ok(()), "{}", set.name);
+            assert_eq!(set.security, set.estimate_security(), "{}", set.name);
+            assert_eq!(ParameterSet::from_id(set.id), Some(*set));
+            assert_eq!(ParameterSet::from_name(set.name), Some(*set));
+
+            assert_ne!(set.id, CUSTOM_PARAMETERS);
+            assert!(REGISTRY[..i].iter().all(|other| other.id < set.id && other.name != set.name));
+        }
+
+        assert_eq!(ParameterSet::from_id(CUSTOM_PARAMETERS), None);
+        assert_eq!(ParameterSet::from_name("custom"), None);
+    }
+
+    #[test]
+    fn erfc_values() {
+        for (x, expected) in [(0.0, 1.0), (0.5, 0.4795001221869535), (1.0, 0.15729920705028513), (3.0, 2.209049699858544e-05), (6.0, 2.1519736712498913e-17), (-1.0, 1.8427007929497148)] {
+            assert!((erfc(x) - expected).abs() <= 2e-7 * expected, "erfc({}) = {}", x, erfc(x));
+        }
+    }
+
+    #[test]
+    fn primes() {
+        let primes: Vec<i32> = (0..50).filter(|&x| is_prime(x)).collect();
+        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]);
+
+        assert!(is_prime(7681) && is_prime(12289) && is_prime(40961) && is_prime(46337));
+        assert!(!is_prime(46339));
+        assert!(is_prime(i32::MAX));
+    }
+
+    #[test]
+    fn rejects_bad_custom_sets() {
+        // Out of range
+        assert_eq!(ParameterSet::custom(9, 0).validate(), Err(Error::UnsupportedParameters));
+        assert_eq!(ParameterSet::custom(31, 25601).validate(), Err(Error::UnsupportedParameters));
+        assert_eq!(ParameterSet::custom(12, 40961).validate(), Err(Error::UnsupportedParameters));
+        assert_eq!(ParameterSet::custom(7, 7681).validate(), Err(Error::UnsupportedParameters));
+
+        // No 2n-th root of unity
+        assert_eq!(ParameterSet::custom(10, 25601).validate(), Err(Error::UnsupportedParameters));
+
+        // A binomial parameter the sampler does not support
+        let mut set = MEDIUM;
+        set.noise = Noise::Binomial(17);
+        assert_eq!(set.validate(), Err(Error::UnsupportedParameters));
+
+        // Larger moduli make failures less likely
+        assert_eq!(ParameterSet::custom(9, 19457).validate(), Err(Error::UnsupportedParameters));
+        assert_eq!(ParameterSet::custom(9, 25601).validate(), Ok(()));
+
+        // Moduli whose products overflow an i32
+        assert_eq!(ParameterSet::custom(10, 40961).validate(), Ok(()));
+        assert_eq!(ParameterSet::custom(11, 2147389441).validate(), Ok(()));
+
+        // Too weak, as q is far larger than the noise
+        assert_eq!(ParameterSet::custom(9, 2147473409).validate(), Err(Error::UnsupportedParameters));
+        assert_eq!(ParameterSet::custom(10, 2147473409).validate(), Err(Error::UnsupportedParameters));
+        assert_eq!(ParameterSet::custom(8, 17921).validate(), Err(Error::UnsupportedParameters));
+    }
+
+    #[test]
+    fn security_estimates() {
+        // NewHope512 and NewHope1024 claim 112 and 257 bits of classical core-SVP security
+        assert_eq!(ParameterSet { exponent: 9, ..HIGH }.estimate_security(), 112);
+        assert_eq!(HIGH.estimate_security(), 259);
+        assert_eq!(ParameterSet::custom(8, 17921).estimate_security(), 48);
+
+        // Security grows with n and falls as q grows relative to the noise
+        assert!(ParameterSet::custom(10, 40961).security > ParameterSet::custom(9, 40961).security);
+        assert!(ParameterSet::custom(9, 40961).security < MEDIUM.security);
+        assert_eq!(ParameterSet::custom(31, 25601).security, 0);
+    }
+
+    #[test]
+    fn failure_probability_is_monotonic() {
+        let (mut narrow, mut wide) = (MEDIUM, MEDIUM);
+        narrow.noise = Noise::Binomial(2);
+        wide.noise = Noise::Binomial(16);
+
+        assert!(narrow.failure_probability() < wide.failure_probability());
+        assert!(ParameterSet::custom(9, 40961).failure_probability() < MEDIUM.failure_probability());
+    }
+}
@@ -0,0 +1,400 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::error::Error;
+use crate::ring::NegacyclicRing;
+
+/// A polynomial of a ring in coefficient space, with every coefficient in [0, q).
+///
+///```
+///# use partav2::ring::*;
+///# use partav2::poly::*;
+/// let ring = NegacyclicRing::new(3, 7681);
+/// let a = Poly::reduced(ring, vec![1, 1]);
+/// let b = Poly::reduced(ring, vec![0, 1]);
+///
+/// assert_eq!((&a * &b).coefficients(), &[0, 1, 1, 0, 0, 0, 0, 0]);
+/// assert_eq!((&a - &b).coefficients(), &[1, 0, 0, 0, 0, 0, 0, 0]);
+/// assert_eq!((-a).coefficients(), &[7680, 7680, 0, 0, 0, 0, 0, 0]);
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poly {
+    ring: NegacyclicRing,
+    coefficients: Vec<i32>
+}
+
+/// A polynomial of a ring in ntt-space, where multiplication is pointwise.
+///
+/// Only rings with a number theoretic transform have polynomials in ntt-space.
+///
+///```
+///# use partav2::ring::*;
+///# use partav2::poly::*;
+/// let ring = NegacyclicRing::new(2, 7681);
+/// let a = Poly::reduced(ring, vec![1, 2, 3, 4]).to_ntt().unwrap();
+///
+/// assert_eq!(a.values(), &[1467, 2807, 3471, 7621]);
+/// assert_eq!(Poly::from_ntt(&a).coefficients(), &[1, 2, 3, 4]);
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NttPoly {
+    ring: NegacyclicRing,
+    values: Vec<i32>
+}
+
+/// Panics if a polynomial passed to a function is not in the expected ring.
+pub(crate) fn expect_ring(expected: NegacyclicRing, ring: NegacyclicRing, function: &str, argument: &str) {
+    assert!(expected == ring, "{}: {}: Must be in the same ring.", function, argument);
+}
+
+impl Poly {
+    /// A polynomial from its coefficients.
+    ///
+    /// Fails if there are not n coefficients, or one is not in [0, q).
+    ///
+    ///```
+    ///# use partav2::ring::*;
+    ///# use partav2::poly::*;
+    ///# use partav2::error::Error;
+    /// let ring = NegacyclicRing::new(1, 7681);
+    /// assert!(Poly::new(ring, vec![0, 7680]).is_ok());
+    /// assert_eq!(Poly::new(ring, vec![0, 7681]), Err(Error::CoefficientOutOfRange));
+    /// assert_eq!(Poly::new(ring, vec![0]), Err(Error::BadHeader));
+    ///```
+    pub fn new(ring: NegacyclicRing, coefficients: Vec<i32>) -> Result<Self, Error> {
+        ring.check(&coefficients)?;
+
+        Ok(Self {
+            ring,
+            coefficients
+        })
+    }
+
+    /// A polynomial from any coefficients, padded to n and reduced into [0, q).
+    ///
+    /// Panics if there are more than n coefficients.
+    ///
+    ///```
+    ///# use partav2::ring::*;
+    ///# use partav2::poly::*;
+    /// let ring = NegacyclicRing::new(2, 7681);
+    /// assert_eq!(Poly::reduced(ring, vec![-1, 7682]).coefficients(), &[7680, 1, 0, 0]);
+    ///```
+    pub fn reduced(ring: NegacyclicRing, coefficients: Vec<i32>) -> Self {
+        assert!(coefficients.len() <= ring.size() as usize, "Poly::reduced: coefficients: Must be at most n.");
+
+        Self {
+            ring,
+            coefficients: ring.pad(coefficients).into_iter().map(|v| ring.reduce(v)).collect()
+        }
+    }
+
+    /// The zero polynomial of a ring
+    pub fn zero(ring: NegacyclicRing) -> Self {
+        Self::reduced(ring, vec![])
+    }
+
+    /// The ring of the polynomial
+    pub fn ring(&self) -> NegacyclicRing {
+        self.ring
+    }
+
+    pub fn coefficients(&self) -> &[i32] {
+        &self.coefficients
+    }
+
+    pub fn into_coefficients(self) -> Vec<i32> {
+        self.coefficients
+    }
+
+    /// Transforms the polynomial to ntt-space, if the ring has a number theoretic transform.
+    pub fn to_ntt(&self) -> Option<NttPoly> {
+        Some(NttPoly {
+            ring: self.ring,
+            values: self.ring.context()?.ntt(self.coefficients.clone())
+        })
+    }
+
+    /// Transforms a polynomial back from ntt-space.
+    pub fn from_ntt(poly: &NttPoly) -> Self {
+        Self {
+            ring: poly.ring,
+            coefficients: poly.ring.context().unwrap().intt(poly.values.clone())
+        }
+    }
+}
+
+impl NttPoly {
+    /// A polynomial from its values in ntt-space.
+    ///
+    /// Fails if there are not n values, or one is not in [0, q), or the ring has no number theoretic
+    /// transform.
+    ///
+    ///```
+    ///# use partav2::ring::*;
+    ///# use partav2::poly::*;
+    ///# use partav2::error::Error;
+    /// assert!(NttPoly::new(NegacyclicRing::new(1, 7681), vec![0, 7680]).is_ok());
+    /// assert_eq!(NttPoly::new(NegacyclicRing::new(1, 7681), vec![0, 7681]), Err(Error::CoefficientOutOfRange));
+    /// assert_eq!(NttPoly::new(NegacyclicRing::new(1, 7680), vec![0, 1]), Err(Error::UnsupportedParameters));
+    ///```
+    pub fn new(ring: NegacyclicRing, values: Vec<i32>) -> Result<Self, Error> {
+        ring.check(&values)?;
+
+        if ring.context().is_none() {
+            return Err(Error::UnsupportedParameters);
+        }
+
+        Ok(Self {
+            ring,
+            values
+        })
+    }
+
+    /// The ring of the polynomial
+    pub fn ring(&self) -> NegacyclicRing {
+        self.ring
+    }
+
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<i32> {
+        self.values
+    }
+}
+
+impl Add for Poly {
+    type Output = Poly;
+
+    fn add(self, rhs: Poly) -> Poly {
+        expect_ring(self.ring, rhs.ring, "Poly::add", "rhs");
+
+        Poly {
+            ring: self.ring,
+            coefficients: self.ring.add(self.coefficients, rhs.coefficients)
+        }
+    }
+}
+
+impl Sub for Poly {
+    type Output = Poly;
+
+    fn sub(self, rhs: Poly) -> Poly {
+        expect_ring(self.ring, rhs.ring, "Poly::sub", "rhs");
+
+        Poly {
+            ring: self.ring,
+            coefficients: self.ring.sub(self.coefficients, rhs.coefficients)
+        }
+    }
+}
+
+/// Multiplication in the ring, through ntt-space, or by Karatsuba multiplication if the ring has no
+/// number theoretic transform.
+impl Mul for Poly {
+    type Output = Poly;
+
+    fn mul(self, rhs: Poly) -> Poly {
+        expect_ring(self.ring, rhs.ring, "Poly::mul", "rhs");
+
+        let coefficients = match self.ring.context() {
+            Some(context) => context.mul(self.coefficients, rhs.coefficients),
+            None => self.ring.mul_karatsuba(self.coefficients, rhs.coefficients)
+        };
+
+        Poly {
+            ring: self.ring,
+            coefficients
+        }
+    }
+}
+
+impl Mul<i32> for Poly {
+    type Output = Poly;
+
+    fn mul(self, rhs: i32) -> Poly {
+        Poly {
+            ring: self.ring,
+            coefficients: self.ring.scalar_mul(self.coefficients, rhs)
+        }
+    }
+}
+
+impl Neg for Poly {
+    type Output = Poly;
+
+    fn neg(self) -> Poly {
+        Poly {
+            ring: self.ring,
+            coefficients: self.ring.neg(self.coefficients)
+        }
+    }
+}
+
+impl Add for NttPoly {
+    type Output = NttPoly;
+
+    fn add(self, rhs: NttPoly) -> NttPoly {
+        expect_ring(self.ring, rhs.ring, "NttPoly::add", "rhs");
+
+        NttPoly {
+            ring: self.ring,
+            values: self.ring.add(self.values, rhs.values)
+        }
+    }
+}
+
+impl Sub for NttPoly {
+    type Output = NttPoly;
+
+    fn sub(self, rhs: NttPoly) -> NttPoly {
+        expect_ring(self.ring, rhs.ring, "NttPoly::sub", "rhs");
+
+        NttPoly {
+            ring: self.ring,
+            values: self.ring.sub(self.values, rhs.values)
+        }
+    }
+}
+
+/// Pointwise multiplication, which is multiplication in the ring.
+impl Mul for NttPoly {
+    type Output = NttPoly;
+
+    fn mul(self, rhs: NttPoly) -> NttPoly {
+        expect_ring(self.ring, rhs.ring, "NttPoly::mul", "rhs");
+
+        NttPoly {
+            ring: self.ring,
+            values: self.ring.mul_ntt(self.values, rhs.values)
+        }
+    }
+}
+
+impl Mul<i32> for NttPoly {
+    type Output = NttPoly;
+
+    fn mul(self, rhs: i32) -> NttPoly {
+        NttPoly {
+            ring: self.ring,
+            values: self.ring.scalar_mul(self.values, rhs)
+        }
+    }
+}
+
+impl Neg for NttPoly {
+    type Output = NttPoly;
+
+    fn neg(self) -> NttPoly {
+        NttPoly {
+            ring: self.ring,
+            values: self.ring.neg(self.values)
+        }
+    }
+}
+
+/// Implements the operators on borrowed polynomials by cloning them
+macro_rules! forward_borrowed {
+    ($t:ty) => {
+        impl Add<&$t> for &$t {
+            type Output = $t;
+            fn add(self, rhs: &$t) -> $t { self.clone() + rhs.clone() }
+        }
+        impl Add<&$t> for $t {
+            type Output = $t;
+            fn add(self, rhs: &$t) -> $t { self + rhs.clone() }
+        }
+        impl Sub<&$t> for &$t {
+            type Output = $t;
+            fn sub(self, rhs: &$t) -> $t { self.clone() - rhs.clone() }
+        }
+        impl Sub<&$t> for $t {
+            type Output = $t;
+            fn sub(self, rhs: &$t) -> $t { self - rhs.clone() }
+        }
+        impl Mul<&$t> for &$t {
+            type Output = $t;
+            fn mul(self, rhs: &$t) -> $t { self.clone() * rhs.clone() }
+        }
+        impl Mul<&$t> for $t {
+            type Output = $t;
+            fn mul(self, rhs: &$t) -> $t { self * rhs.clone() }
+        }
+        impl Mul<i32> for &$t {
+            type Output = $t;
+            fn mul(self, rhs: i32) -> $t { self.clone() * rhs }
+        }
+        impl Neg for &$t {
+            type Output = $t;
+            fn neg(self) -> $t { -self.clone() }
+        }
+    };
+}
+
+forward_borrowed!(Poly);
+forward_borrowed!(NttPoly);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::Rand;
+
+    #[test]
+    fn operators_match_ring() {
+        let ring = NegacyclicRing::new(9, 25601);
+        let mut rng = Rand::from_seed([3; 32]);
+
+        for _ in 0..10 {
+            let a = Poly::new(ring, ring.rand_with_rng(&mut rng)).unwrap();
+            let b = Poly::new(ring, ring.rand_with_rng(&mut rng)).unwrap();
+
+            let product = ring.mul(a.coefficients().to_vec(), b.coefficients().to_vec()).unwrap();
+            assert_eq!((&a * &b).into_coefficients(), product);
+            assert_eq!(Poly::from_ntt(&(a.to_ntt().unwrap() * b.to_ntt().unwrap())), &a * &b);
+
+            let sum = ring.add(a.coefficients().to_vec(), b.coefficients().to_vec());
+            assert_eq!((&a + &b).into_coefficients(), sum);
+            assert_eq!(Poly::from_ntt(&(a.to_ntt().unwrap() + b.to_ntt().unwrap())), &a + &b);
+
+            assert_eq!(&(&a - &b) + &b, a);
+            assert_eq!(-&a + a.clone(), Poly::zero(ring));
+            assert_eq!(&a * 3, &(&a + &a) + &a);
+            assert_eq!(&a * -1, -&a);
+            assert_eq!(a.to_ntt().unwrap() * 2, a.to_ntt().unwrap() + a.to_ntt().unwrap());
+        }
+    }
+
+    #[test]
+    fn transform_round_trip() {
+        let ring = NegacyclicRing::new(8, 7681);
+        let a = Poly::new(ring, ring.rand()).unwrap();
+
+        assert_eq!(Poly::from_ntt(&a.to_ntt().unwrap()), a);
+        assert_eq!(a.to_ntt().unwrap().ring(), ring);
+
+        assert_eq!(Poly::zero(NegacyclicRing::new(8, 7680)).to_ntt(), None);
+    }
+
+    #[test]
+    fn multiplication_without_a_transform() {
+        let ring = NegacyclicRing::new(8, 3329);
+        let a = Poly::new(ring, ring.rand()).unwrap();
+        let b = Poly::new(ring, ring.rand()).unwrap();
+
+        assert_eq!(a.to_ntt(), None);
+        assert_eq!((&a * &b).into_coefficients(), ring.mul_schoolbook(a.into_coefficients(), b.into_coefficients()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Poly::add: rhs: Must be in the same ring.")]
+    fn rings_must_match() {
+        let _ = Poly::zero(NegacyclicRing::new(8, 7681)) + Poly::zero(NegacyclicRing::new(8, 12289));
+    }
+
+    #[test]
+    #[should_panic(expected = "Poly::reduced: coefficients: Must be at most n.")]
+    fn too_many_coefficients() {
+        Poly::reduced(NegacyclicRing::new(1, 7681), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,183 @@
+/// The mask of a 26-bit limb
+const LIMB: u32 = 0x3ffffff;
+
+/// The Poly1305 one-time authenticator, with its accumulator in 26-bit limbs.
+///
+/// A key must never be used for more than one message.
+///
+/// ```
+///# use partav2::poly1305::*;
+/// let mut mac = Poly1305::new([1; 32]);
+/// mac.absorb(b"Cryptographic ");
+/// mac.absorb(b"Forum Research Group");
+/// assert_eq!(mac.finalise(), authenticate([1; 32], b"Cryptographic Forum Research Group"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Poly1305 {
+    r: [u32; 5],
+    pad: [u32; 4],
+    h: [u32; 5],
+
+    /// Input not yet forming a whole block
+    buffer: [u8; 16],
+    buffered: usize
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[0..4].try_into().unwrap())
+}
+
+impl Poly1305 {
+    /// A new authenticator from a 32 byte one-time key (r, then s).
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            // r is clamped as it is split into limbs
+            r: [
+                le32(&key[0..]) & 0x3ffffff,
+                (le32(&key[3..]) >> 2) & 0x3ffff03,
+                (le32(&key[6..]) >> 4) & 0x3ffc0ff,
+                (le32(&key[9..]) >> 6) & 0x3f03fff,
+                (le32(&key[12..]) >> 8) & 0x00fffff
+            ],
+            pad: [le32(&key[16..]), le32(&key[20..]), le32(&key[24..]), le32(&key[28..])],
+            h: [0; 5],
+
+            buffer: [0; 16],
+            buffered: 0
+        }
+    }
+
+    /// Adds a 16 byte block to the accumulator and multiplies by r, modulo 2^130 - 5.
+    ///
+    /// hibit is the 2^128 bit, set for every block except a padded final block.
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(|r| r as u64);
+        let [s1, s2, s3, s4] = [r1 * 5, r2 * 5, r3 * 5, r4 * 5];
+
+        let h0 = (self.h[0] + (le32(&m[0..]) & LIMB)) as u64;
+        let h1 = (self.h[1] + ((le32(&m[3..]) >> 2) & LIMB)) as u64;
+        let h2 = (self.h[2] + ((le32(&m[6..]) >> 4) & LIMB)) as u64;
+        let h3 = (self.h[3] + ((le32(&m[9..]) >> 6) & LIMB)) as u64;
+        let h4 = (self.h[4] + ((le32(&m[12..]) >> 8) | hibit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        d2 += d1 >> 26;
+        d3 += d2 >> 26;
+        d4 += d3 >> 26;
+
+        let mut h0 = (d0 as u32 & LIMB) + (d4 >> 26) as u32 * 5;
+        let h1 = (d1 as u32 & LIMB) + (h0 >> 26);
+        h0 &= LIMB;
+
+        self.h = [h0, h1, d2 as u32 & LIMB, d3 as u32 & LIMB, d4 as u32 & LIMB];
+    }
+
+    /// Absorbs more of the message.
+    pub fn absorb(&mut self, input: &[u8]) {
+        for byte in input {
+            self.buffer[self.buffered] = *byte;
+            self.buffered += 1;
+
+            if self.buffered == 16 {
+                let block = self.buffer;
+                self.block(&block, 1 << 24);
+                self.buffered = 0;
+            }
+        }
+    }
+
+    /// Returns the 16 byte tag of the message.
+    pub fn finalise(mut self) -> [u8; 16] {
+        if self.buffered > 0 {
+            let mut block = [0u8; 16];
+            block[..self.buffered].copy_from_slice(&self.buffer[..self.buffered]);
+            block[self.buffered] = 1;
+
+            self.block(&block, 0);
+        }
+
+        let mut h = self.h;
+
+        // Fully carry h
+        let mut c;
+        c = h[1] >> 26; h[1] &= LIMB; h[2] += c;
+        c = h[2] >> 26; h[2] &= LIMB; h[3] += c;
+        c = h[3] >> 26; h[3] &= LIMB; h[4] += c;
+        c = h[4] >> 26; h[4] &= LIMB; h[0] += c * 5;
+        c = h[0] >> 26; h[0] &= LIMB; h[1] += c;
+
+        // g = h - p, chosen over h without branching if it is not negative
+        let mut g = [0u32; 5];
+        g[0] = h[0].wrapping_add(5); c = g[0] >> 26; g[0] &= LIMB;
+        g[1] = h[1] + c; c = g[1] >> 26; g[1] &= LIMB;
+        g[2] = h[2] + c; c = g[2] >> 26; g[2] &= LIMB;
+        g[3] = h[3] + c; c = g[3] >> 26; g[3] &= LIMB;
+        g[4] = (h[4] + c).wrapping_sub(1 << 26);
+
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        // h mod 2^128, then add the pad
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8)
+        ];
+
+        let mut tag = [0u8; 16];
+        let mut carry = 0u64;
+        for i in 0..4 {
+            carry += words[i] as u64 + self.pad[i] as u64;
+            tag[i * 4 .. i * 4 + 4].copy_from_slice(&(carry as u32).to_le_bytes());
+            carry >>= 32;
+        }
+
+        tag
+    }
+}
+
+/// The Poly1305 tag of a whole message.
+///
+/// ```
+///# use partav2::poly1305::*;
+/// // RFC 8439 section 2.5.2
+/// let key = [
+///     0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5, 0x06, 0xa8,
+///     0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf, 0x41, 0x49, 0xf5, 0x1b
+/// ];
+/// assert_eq!(
+///     authenticate(key, b"Cryptographic Forum Research Group"),
+///     [0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01, 0x27, 0xa9]
+/// );
+/// ```
+pub fn authenticate(key: [u8; 32], message: &[u8]) -> [u8; 16] {
+    let mut mac = Poly1305::new(key);
+    mac.absorb(message);
+
+    mac.finalise()
+}
+
+/// Compares two tags in constant time.
+///
+/// ```
+///# use partav2::poly1305::*;
+/// assert!(verify(&[1; 16], &[1; 16]));
+/// assert!(!verify(&[1; 16], &[2; 16]));
+/// ```
+pub fn verify(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    let mut difference = 0u8;
+    for (a, b) in a.iter().zip(b) {
+        difference |= a ^ b;
+    }
+
+    difference == 0
+}
@@ -1,53 +1,127 @@
-use std::time::SystemTime;
-use std::process;
-use rand::TryRngCore;
+use std::fmt;
+use rand::{CryptoRng, RngCore, TryRngCore};
 use rand::rngs::OsRng;
 
 use crate::chacha::ChaCha20;
+use crate::secret::wipe;
 
-/// Random generation
-#[derive(Debug)]
+/// The number of ChaCha20 blocks generated per refill
+const BLOCKS: usize = 16;
+
+/// The bytes generated per refill, the first 32 of which replace the key
+const REFILL_SIZE: usize = BLOCKS * 64;
+
+/// Random generation, a ChaCha20 DRBG seeded with 256 bits of OS entropy.
+///
+/// The key is replaced after every refill (fast key erasure), and output bytes are erased from
+/// the buffer as they are used, so a later compromise of the state does not reveal earlier output.
+///
+///```
+///# use partav2::rand::*;
+/// let mut rnd = Rand::new();
+///
+/// let mut bytes = [0u8; 64];
+/// rnd.fill_bytes(&mut bytes);
+/// assert_ne!(bytes, [0; 64]);
+///```
 pub struct Rand {
-    pub seed: [u32; 8],
-    pub count: u32,
+    key: [u8; 32],
+
+    /// The number of refills since the generator was created, used as the nonce
+    count: u64,
 
-    pub buffer: Vec<u32>
+    buffer: [u8; REFILL_SIZE],
+    /// The offset of the next unused byte in the buffer
+    offset: usize
 }
 
 impl Rand {
-    /// Randomly seeds a new rand
+    /// Randomly seeds a new rand from the operating system
     ///
     ///```
     ///# use partav2::rand::*;
     /// Rand::new();
     ///```
     pub fn new() -> Self {
-        let mut seed = [0u32; 8];
+        Self::from_seed(os_entropy())
+    }
 
-        let epoch: u128 = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
-        let pid = process::id();
-        let vec = vec![1, 2, 3, 4];
-        let heap = vec.as_ptr() as usize;
+    /// A deterministic rand from a 32 byte seed, for reproducible tests.
+    ///
+    /// The same seed always gives the same output, so it must only be used with a secret,
+    /// uniformly random seed when generating keys.
+    ///
+    ///```
+    ///# use partav2::rand::*;
+    /// let mut a = Rand::from_seed([7; 32]);
+    /// let mut b = Rand::from_seed([7; 32]);
+    /// assert_eq!(a.sample(), b.sample());
+    ///```
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            key: seed,
+            count: 0,
 
-        seed[0] = u32::from_le_bytes(epoch.to_le_bytes()[0..4].try_into().unwrap());
-        seed[1] = u32::from_le_bytes(epoch.to_le_bytes()[4..8].try_into().unwrap());
+            buffer: [0; REFILL_SIZE],
+            offset: REFILL_SIZE
+        }
+    }
+
+    /// Mixes fresh OS entropy into the key, and discards any buffered output
+    ///
+    ///```
+    ///# use partav2::rand::*;
+    /// let mut rnd = Rand::new();
+    /// rnd.reseed();
+    ///```
+    pub fn reseed(&mut self) {
+        for (k, e) in self.key.iter_mut().zip(os_entropy()) {
+            *k ^= e;
+        }
 
-        seed[2] = u32::from_le_bytes(heap.to_le_bytes()[0..4].try_into().unwrap());
-        seed[3] = u32::from_le_bytes(heap.to_le_bytes()[4..8].try_into().unwrap());
+        self.buffer = [0; REFILL_SIZE];
+        self.offset = REFILL_SIZE;
+    }
 
-        seed[4] = pid;
-        seed[5] = 0x1f2e3d4c;
+    /// Generates the next buffer of output, and replaces the key with the start of it
+    fn refill(&mut self) {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.count.to_le_bytes());
 
-        let mut rnd: [u8; 8] = [0; 8];
-        OsRng.try_fill_bytes(&mut rnd).unwrap();
-        seed[6] = u32::from_le_bytes(rnd[0..4].try_into().unwrap());
-        seed[7] = u32::from_le_bytes(rnd[4..8].try_into().unwrap());
+        self.count = self.count.checked_add(1).expect("Rand::refill: The refill counter would wrap.");
 
-        Self {
-            seed: seed,
-            count: 1,
+        self.buffer = [0; REFILL_SIZE];
+        ChaCha20::from_bytes(self.key, 0, nonce).apply_keystream(&mut self.buffer);
 
-            buffer: vec![]
+        self.key.copy_from_slice(&self.buffer[..32]);
+        self.buffer[..32].fill(0);
+        self.offset = 32;
+    }
+
+    /// Fills a buffer with random bytes
+    ///
+    ///```
+    ///# use partav2::rand::*;
+    /// let mut rnd = Rand::new();
+    /// let mut bytes = [0u8; 32];
+    /// rnd.fill_bytes(&mut bytes);
+    ///```
+    pub fn fill_bytes(&mut self, out: &mut [u8]) {
+        let mut filled = 0;
+
+        while filled < out.len() {
+            if self.offset == REFILL_SIZE {
+                self.refill();
+            }
+
+            let len = (out.len() - filled).min(REFILL_SIZE - self.offset);
+            let used = &mut self.buffer[self.offset..self.offset + len];
+
+            out[filled..filled + len].copy_from_slice(used);
+            used.fill(0);
+
+            filled += len;
+            self.offset += len;
         }
     }
 
@@ -59,35 +133,152 @@ impl Rand {
     /// rnd.sample();
     ///```
     pub fn sample(&mut self) -> [u32; 16] {
-        let mut nonce: [u32; 3] = [0; 3];
-
-        let epoch: u128 = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
-        let mut rnd: [u8; 4] = [0; 4];
-        OsRng.try_fill_bytes(&mut rnd).unwrap();
-
-        nonce[0] = u32::from_le_bytes(epoch.to_le_bytes()[0..4].try_into().unwrap());
-        nonce[1] = u32::from_le_bytes(epoch.to_le_bytes()[4..8].try_into().unwrap());
-        nonce[2] = u32::from_le_bytes(rnd);
+        let mut bytes = [0u8; 64];
+        self.fill_bytes(&mut bytes);
 
-        let chacha = ChaCha20::new(self.seed, self.count, nonce);
-        self.count += 1;
-
-        chacha.block().state
+        core::array::from_fn(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
     }
 
-    /// Takes a random number from the buffer, regenerating if necessary
+    /// A random number
     ///
     ///```
     ///# use partav2::rand::*;
     /// let mut rnd = Rand::new();
-    /// rnd.next();
+    /// rnd.next_i32();
     ///```
-    pub fn next(&mut self) -> i32 {
-        if self.buffer.len() == 0 {
-            self.buffer = self.sample().into();
+    pub fn next_i32(&mut self) -> i32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+
+        i32::from_le_bytes(bytes)
+    }
+}
+
+impl RngCore for Rand {
+    fn next_u32(&mut self) -> u32 {
+        self.next_i32() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        Rand::fill_bytes(self, &mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        Rand::fill_bytes(self, dst);
+    }
+}
+
+/// Rand is seeded from the operating system and erases its key, so it is fit for generating keys
+impl CryptoRng for Rand {}
+
+impl Default for Rand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Rand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rand({} refills)", self.count)
+    }
+}
+
+impl Drop for Rand {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+        wipe(&mut self.buffer);
+    }
+}
+
+/// 256 bits from the operating system
+fn os_entropy() -> [u8; 32] {
+    let mut entropy = [0u8; 32];
+    OsRng.try_fill_bytes(&mut entropy).expect("Rand: The operating system random generator failed.");
+
+    entropy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn seeded_known_answer() {
+        // The first block under the zero key is the RFC 8439 A.1 keystream, of which the
+        // first 32 bytes become the next key
+        let mut rnd = Rand::from_seed([0; 32]);
+
+        let mut bytes = [0u8; 32];
+        rnd.fill_bytes(&mut bytes);
+        assert_eq!(bytes.to_vec(), hex("da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"));
+        assert_eq!(rnd.key.to_vec(), hex("76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7"));
+    }
+
+    #[test]
+    fn fast_key_erasure() {
+        let mut rnd = Rand::new();
+        let key = rnd.key;
+
+        let mut bytes = [0u8; 10];
+        rnd.fill_bytes(&mut bytes);
+
+        // The key was replaced, and the output erased from the buffer
+        assert_ne!(rnd.key, key);
+        assert_eq!(rnd.count, 1);
+        assert_eq!(rnd.buffer[..42], [0; 42]);
+        assert_ne!(rnd.buffer[42..], [0; REFILL_SIZE - 42]);
+    }
+
+    #[test]
+    fn chunking_does_not_change_output() {
+        let mut a = Rand::from_seed([1; 32]);
+        let mut b = Rand::from_seed([1; 32]);
+
+        let mut whole = vec![0u8; 5000];
+        a.fill_bytes(&mut whole);
+
+        let mut parts = vec![0u8; 5000];
+        for part in parts.chunks_mut(7) {
+            b.fill_bytes(part);
         }
 
-        // We have just checked if empty
-        self.buffer.pop().unwrap() as i32
+        assert_eq!(whole, parts);
+        assert_eq!(a.count, 6);
+    }
+
+    #[test]
+    fn reseed() {
+        let mut a = Rand::from_seed([2; 32]);
+        let mut b = Rand::from_seed([2; 32]);
+
+        b.reseed();
+        assert_ne!(a.sample(), b.sample());
+    }
+
+    #[test]
+    fn rng_core_matches_inherent() {
+        let mut a = Rand::from_seed([3; 32]);
+        let mut b = Rand::from_seed([3; 32]);
+
+        let mut bytes = [0u8; 12];
+        a.fill_bytes(&mut bytes);
+
+        assert_eq!(RngCore::next_u32(&mut b).to_le_bytes(), bytes[0..4]);
+        assert_eq!(RngCore::next_u64(&mut b).to_le_bytes(), bytes[4..12]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn counter_cannot_wrap() {
+        let mut rnd = Rand::new();
+        rnd.count = u64::MAX;
+        rnd.next_i32();
     }
 }
@@ -0,0 +1,178 @@
+/// How products are reduced modulo q in a ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reduction {
+    /// Hardware division, which works for any modulus and any input
+    Division,
+    /// Barrett reduction everywhere
+    Barrett,
+    /// Montgomery reduction in the number theoretic transform, whose twiddle factors are kept in
+    /// Montgomery form, and Barrett reduction everywhere else. Needs an odd modulus.
+    Montgomery
+}
+
+/// Subtracts q from r if r >= q, without branching, for r < 2q.
+///
+///```
+///# use partav2::reduce::*;
+/// assert_eq!(subtract_modulus(7681, 7681), 0);
+/// assert_eq!(subtract_modulus(7680, 7681), 7680);
+///```
+pub const fn subtract_modulus(r: u32, q: u32) -> u32 {
+    let d = r.wrapping_sub(q);
+
+    // All ones if r < q, when d has wrapped past 2^31
+    let mask = (d >> 31).wrapping_neg();
+
+    d.wrapping_add(q & mask)
+}
+
+/// Barrett reduction by a fixed modulus, with a precomputed factor of floor(2^64 / q).
+///
+///```
+///# use partav2::reduce::*;
+/// let barrett = Barrett::new(7681);
+/// assert_eq!(barrett.reduce(7680 * 7680), 1);
+/// assert_eq!(barrett.reduce(u64::MAX), (u64::MAX % 7681) as u32);
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Barrett {
+    modulus: u32,
+    factor: u64
+}
+
+impl Barrett {
+    /// Precomputes the factor for a modulus.
+    ///
+    /// Panics if the modulus is less than 2.
+    pub const fn new(q: u32) -> Self {
+        assert!(q >= 2, "Barrett::new: q: Must be at least 2.");
+
+        Self {
+            modulus: q,
+            factor: ((1u128 << 64) / q as u128) as u64
+        }
+    }
+
+    /// Returns x mod q, for any x.
+    pub fn reduce(&self, x: u64) -> u32 {
+        // The quotient is at most one below floor(x / q), so r < 2q
+        let quotient = ((x as u128 * self.factor as u128) >> 64) as u64;
+        let r = x - quotient * self.modulus as u64;
+
+        subtract_modulus(r as u32, self.modulus)
+    }
+}
+
+/// Montgomery reduction by a fixed odd modulus, with R = 2^32.
+///
+///```
+///# use partav2::reduce::*;
+/// let montgomery = Montgomery::new(7681);
+///
+/// // Multiplying by a value in Montgomery form gives a plain product
+/// let b = montgomery.to_montgomery(1234);
+/// assert_eq!(montgomery.mul(5678, b), 5678 * 1234 % 7681);
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Montgomery {
+    modulus: u32,
+    /// -q^-1 mod R
+    inverse: u32,
+    /// R^2 mod q
+    r2: u32
+}
+
+impl Montgomery {
+    /// Precomputes the constants for an odd modulus below 2^31.
+    ///
+    /// Panics if the modulus is even or too large.
+    pub const fn new(q: u32) -> Self {
+        assert!(q % 2 == 1 && q < 1 << 31, "Montgomery::new: q: Must be odd and below 2^31.");
+
+        // Newton's iteration doubles the correct low bits of q^-1 each step, from 3 (q * q = 1 mod 8)
+        let mut inverse = q;
+        let mut i = 0;
+        while i < 4 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(q.wrapping_mul(inverse)));
+            i += 1;
+        }
+
+        Self {
+            modulus: q,
+            inverse: inverse.wrapping_neg(),
+            r2: ((1u128 << 64) % q as u128) as u32
+        }
+    }
+
+    /// Returns x R^-1 mod q, for x < q R.
+    pub fn reduce(&self, x: u64) -> u32 {
+        let m = (x as u32).wrapping_mul(self.inverse);
+
+        // x + m q is divisible by R, and below 2 q R
+        let t = ((x + m as u64 * self.modulus as u64) >> 32) as u32;
+
+        subtract_modulus(t, self.modulus)
+    }
+
+    /// Returns a R mod q, the Montgomery form of a, for a < q.
+    pub fn to_montgomery(&self, a: u32) -> u32 {
+        self.reduce(a as u64 * self.r2 as u64)
+    }
+
+    /// Returns a b mod q, for a < q and b in Montgomery form.
+    pub fn mul(&self, a: u32, b: u32) -> u32 {
+        self.reduce(a as u64 * b as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::Rand;
+
+    #[test]
+    fn barrett_matches_division() {
+        let mut rng = Rand::from_seed([1; 32]);
+
+        for q in [2, 3, 7681, 12289, 25601, 65536, 2147473409, i32::MAX as u32] {
+            let barrett = Barrett::new(q);
+
+            for x in [0, 1, q as u64 - 1, q as u64, (q as u64 - 1).pow(2), u64::MAX] {
+                assert_eq!(barrett.reduce(x) as u64, x % q as u64, "{} mod {}", x, q);
+            }
+            for _ in 0..10000 {
+                let x = u64::from_le_bytes(core::array::from_fn(|_| rng.next_i32() as u8));
+                assert_eq!(barrett.reduce(x) as u64, x % q as u64, "{} mod {}", x, q);
+            }
+        }
+    }
+
+    #[test]
+    fn montgomery_matches_division() {
+        let mut rng = Rand::from_seed([2; 32]);
+
+        for q in [3, 7681, 12289, 25601, 2147473409, i32::MAX as u32] {
+            let montgomery = Montgomery::new(q);
+            assert_eq!(q.wrapping_mul(montgomery.inverse), u32::MAX);
+
+            for _ in 0..10000 {
+                let a = rng.next_i32() as u32 % q;
+                let b = rng.next_i32() as u32 % q;
+
+                assert_eq!(montgomery.mul(a, montgomery.to_montgomery(b)) as u64, a as u64 * b as u64 % q as u64);
+            }
+
+            assert_eq!(montgomery.mul(q - 1, montgomery.to_montgomery(q - 1)), 1);
+            assert_eq!(montgomery.reduce(0), 0);
+        }
+    }
+
+    #[test]
+    fn subtract_modulus_is_exact() {
+        for q in [2, 7681, 2147473409, i32::MAX as u32] {
+            for r in [0, 1, q - 1, q, q + 1, 2 * q - 1] {
+                assert_eq!(subtract_modulus(r, q), r % q);
+            }
+        }
+    }
+}
@@ -1,18 +1,81 @@
+use rand::CryptoRng;
+
+use crate::gaussian::DiscreteGaussian;
 use crate::rand::Rand;
+use crate::reduce::{Barrett, Montgomery, Reduction, subtract_modulus};
+use crate::sha3::Shake;
 
 /// A negcyclic polynomial ring type
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NegacyclicRing {
     pub modulus: i32,
-    pub exponent: u32
+    pub exponent: u32,
+    /// How products are reduced modulo q
+    pub reduction: Reduction,
+
+    barrett: Option<Barrett>,
+    montgomery: Option<Montgomery>
 }
 
 impl NegacyclicRing {
     /// Creates a new negacyclic ring (form X^(2^n) + 1)_q from n and q.
-    pub fn new(n: u32, q: i32) -> Self {
+    ///
+    /// Products are reduced with Montgomery reduction if q is odd, or Barrett reduction if it is even.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::reduce::*;
+    /// assert_eq!(NegacyclicRing::new(9, 25601).reduction, Reduction::Montgomery);
+    /// assert_eq!(NegacyclicRing::new(4, 1 << 30).reduction, Reduction::Barrett);
+    /// ```
+    pub const fn new(n: u32, q: i32) -> Self {
         Self {
             modulus: q,
-            exponent: n
+            exponent: n,
+            reduction: if q < 2 { Reduction::Division } else if q % 2 == 1 { Reduction::Montgomery } else { Reduction::Barrett },
+
+            barrett: if q >= 2 { Some(Barrett::new(q as u32)) } else { None },
+            montgomery: if q >= 3 && q % 2 == 1 { Some(Montgomery::new(q as u32)) } else { None }
+        }
+    }
+
+    /// The same ring, with products reduced by the given strategy.
+    ///
+    /// Panics if the strategy cannot be used with the modulus.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::reduce::*;
+    /// let ring = NegacyclicRing::new(9, 25601).with_reduction(Reduction::Division);
+    /// assert_eq!(ring.mul_mod(25600, 25600), 1);
+    /// ```
+    pub fn with_reduction(self, reduction: Reduction) -> Self {
+        let supported = match reduction {
+            Reduction::Division => true,
+            Reduction::Barrett => self.barrett.is_some(),
+            Reduction::Montgomery => self.montgomery.is_some()
+        };
+        assert!(supported, "with_reduction: reduction: Not supported by the modulus.");
+
+        Self {
+            reduction,
+            ..self
+        }
+    }
+
+    /// The Montgomery constants of the ring, if it uses Montgomery reduction
+    pub(crate) fn montgomery(&self) -> Option<Montgomery> {
+        match self.reduction {
+            Reduction::Montgomery => self.montgomery,
+            _ => None
+        }
+    }
+
+    /// The Barrett constants of the ring, unless it uses division
+    fn barrett(&self) -> Option<Barrett> {
+        match self.reduction {
+            Reduction::Division => None,
+            _ => self.barrett
         }
     }
 
@@ -26,7 +89,18 @@ impl NegacyclicRing {
     /// )
     /// ```
     pub fn size(&self) -> i32 {
-        return (2_i32).pow(self.exponent);
+        (2_i32).pow(self.exponent)
+    }
+
+    /// Gives the number of bits needed for a coefficient, or ceil(log2 q).
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// assert_eq!(NegacyclicRing::new(9, 25601).coefficient_bits(), 15);
+    /// assert_eq!(NegacyclicRing::new(9, 4096).coefficient_bits(), 12);
+    /// ```
+    pub fn coefficient_bits(&self) -> u32 {
+        32 - (self.modulus - 1).leading_zeros()
     }
 
     /// Returns x ^ y with respect to the Negacyclic ring.
@@ -41,25 +115,119 @@ impl NegacyclicRing {
     /// )
     /// ```
     pub fn power(&self, x: i32, y: i32) -> i32 {
-        match y {
-            ..0 => panic!("termPower: y: Argument less than zero. Negative exponents are prohibited."),
-            0 => 1,
-            0.. => {
-                let mut working: i32 = 1;
-
-                let mut base: i32 = x % self.modulus;
-                let mut exp: i32 = y;
-                while exp > 0 {
-                    if exp % 2 == 1 {
-                        working = (working * base) % self.modulus;
-                    }
-                    exp /= 2;
-                    base = (base * base) % self.modulus;
-                }
+        if y < 0 {
+            panic!("termPower: y: Argument less than zero. Negative exponents are prohibited.");
+        }
+
+        let mut working: i32 = 1;
+
+        // Every bit of y is visited and both products are computed, so the time does not depend on y
+        let mut base: i32 = self.reduce(x);
+        for i in 0..31 {
+            let mask = -((y >> i) & 1);
+            let product = self.mul_mod(working, base);
+
+            working = (product & mask) | (working & !mask);
+            base = self.mul_mod(base, base);
+        }
+
+        working
+    }
+
+    /// Reduces any value into [0, q), without division unless the ring uses division.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(9, 25601);
+    /// assert_eq!(ring.reduce(-1), 25600);
+    /// assert_eq!(ring.reduce(i32::MAX), i32::MAX % 25601);
+    /// ```
+    pub fn reduce(&self, v: i32) -> i32 {
+        match self.barrett() {
+            // Adding q 2^32 makes any i32 positive without changing it modulo q
+            Some(barrett) => barrett.reduce((v as i64 + ((self.modulus as i64) << 32)) as u64) as i32,
+            None => v.rem_euclid(self.modulus)
+        }
+    }
+
+    /// Returns a + b with respect to the modulus, widened so it cannot overflow.
+    ///
+    /// Both must be reduced into [0, q), unless the ring uses division.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(9, 2147473409);
+    /// assert_eq!(ring.add_mod(2147473408, 2147473408), 2147473407);
+    /// ```
+    pub fn add_mod(&self, a: i32, b: i32) -> i32 {
+        match self.barrett() {
+            Some(_) => subtract_modulus(a as u32 + b as u32, self.modulus as u32) as i32,
+            None => (a as i64 + b as i64).rem_euclid(self.modulus as i64) as i32
+        }
+    }
+
+    /// Returns a - b with respect to the modulus, widened so it cannot overflow.
+    ///
+    /// Both must be reduced into [0, q), unless the ring uses division.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(9, 2147473409);
+    /// assert_eq!(ring.sub_mod(0, 2147473408), 1);
+    /// ```
+    pub fn sub_mod(&self, a: i32, b: i32) -> i32 {
+        match self.barrett() {
+            Some(_) => subtract_modulus(a as u32 + (self.modulus - b) as u32, self.modulus as u32) as i32,
+            None => (a as i64 - b as i64).rem_euclid(self.modulus as i64) as i32
+        }
+    }
+
+    /// Returns a * b with respect to the modulus, widened so it cannot overflow.
+    ///
+    /// Both must be reduced into [0, q), unless the ring uses division.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(9, 2147473409);
+    /// assert_eq!(ring.mul_mod(2147473408, 2147473408), 1); // (-1) * (-1)
+    /// ```
+    pub fn mul_mod(&self, a: i32, b: i32) -> i32 {
+        match self.barrett() {
+            Some(barrett) => barrett.reduce(a as u64 * b as u64) as i32,
+            None => (a as i64 * b as i64).rem_euclid(self.modulus as i64) as i32
+        }
+    }
+
+    /// Returns an element of exactly the given order, a power of two, if the modulus has one.
+    ///
+    /// Raises successive candidates to (q - 1) / order, so for a prime modulus only a few are tried.
+    fn root_of_order(&self, order: i32) -> Option<i32> {
+        if order == 1 {
+            return Some(1);
+        }
+        if (self.modulus - 1) % order != 0 {
+            return None;
+        }
+
+        (2..self.modulus)
+            .map(|x| self.power(x, (self.modulus - 1) / order))
+            .find(|&root| self.power(root, order) == 1 && self.power(root, order / 2) != 1)
+    }
+
+    /// Returns the smallest odd power of a root below the given order, which are the roots of that order.
+    fn smallest_odd_power(&self, root: i32, order: i32, accept: impl Fn(i32) -> bool) -> Option<i32> {
+        let step = self.mul_mod(root, root);
 
-                working
+        let mut working = root;
+        let mut smallest = None;
+        for _ in (1..order).step_by(2) {
+            if accept(working) && smallest.is_none_or(|s| working < s) {
+                smallest = Some(working);
             }
+            working = self.mul_mod(working, step);
         }
+
+        smallest
     }
 
     /// Returns the primitive nth root of unity (if one exists).
@@ -76,21 +244,14 @@ impl NegacyclicRing {
     /// );
     /// ```
     pub fn primitiventhunity(&self) -> Option<i32> {
-        for root in 0..self.modulus {
-            if self.power(root, self.size().try_into().unwrap()) == 1 {
-                let mut taken = false;
-                for k in 1..self.size().try_into().unwrap() {
-                    if self.power(root, k) == 1 {
-                        taken = true;
-                    }
-                }
-                if !taken {
-                    return Some(root);
-                }
-            }
+        let n = self.size();
+        let root = self.root_of_order(n)?;
+
+        if n == 1 {
+            return Some(root);
         }
 
-        None
+        self.smallest_odd_power(root, n, |_| true)
     }
 
     /// Returns the primitive 2nth root of unity (if one exists).
@@ -107,15 +268,11 @@ impl NegacyclicRing {
     /// );
     /// ```
     pub fn primitive2nthunity(&self) -> Option<i32> {
-        let nthunity = self.primitiventhunity().unwrap();
+        let nthunity = self.primitiventhunity()?;
+        let order = self.size().checked_mul(2)?;
+        let root = self.root_of_order(order)?;
 
-        for root in 0..self.modulus {
-            if self.power(root, 2) == nthunity && self.power(root, self.size().try_into().unwrap()) == self.modulus - 1 {
-                return Some(root);
-            }
-        }
-
-        None
+        self.smallest_odd_power(root, order, |z| self.mul_mod(z, z) == nthunity && self.power(z, self.size()) == self.modulus - 1)
     }
 
     /// Pads a polynomial to the correct length in a negacyclic ring.
@@ -173,17 +330,27 @@ impl NegacyclicRing {
     /// );
     /// ```
     pub fn inverse(&self, x: i32) -> Option<i32> {
-        for i in 1..self.modulus {
-            if (i * x) % self.modulus == 1 {
-                return Some(i);
-            }
+        // Extended Euclidean algorithm
+        let (mut r0, mut r1) = (self.modulus as i64, x.rem_euclid(self.modulus) as i64);
+        let (mut t0, mut t1) = (0i64, 1i64);
+
+        while r1 != 0 {
+            let quotient = r0 / r1;
+            (r0, r1) = (r1, r0 - quotient * r1);
+            (t0, t1) = (t1, t0 - quotient * t1);
         }
 
-        None
+        if r0 != 1 {
+            return None;
+        }
+
+        Some(t0.rem_euclid(self.modulus as i64) as i32)
     }
 
     /// Number theoretic transform in the negacyclic ring.
     ///
+    /// Uses the shared NTT context of the ring, see [`NegacyclicRing::context`].
+    ///
     /// ```
     ///# use partav2::ring::*;
     /// assert_eq!(
@@ -192,48 +359,13 @@ impl NegacyclicRing {
     /// );
     /// ```
     pub fn ntt(&self, val: Vec<i32>) -> Option<Vec<i32>> {
-        let mut out = self.pad(val);
-
-        let rootunity = self.primitive2nthunity().unwrap();
-        let k: usize = self.exponent.try_into().unwrap();
-        let q = self.modulus;
-        let n: usize = self.size().try_into().unwrap();
-
-        let mut t: usize = n;
-        let mut m: usize = 1;
-        while m < n {
-            t /= 2;
-
-            for i in 0..m {
-                let j1 = 2 * i * t;
-                let j2 = j1 + t;
-                let s = self.power(rootunity, NegacyclicRing::bit_reverse((m + i).try_into().unwrap(), k.try_into().unwrap()));
-
-                for j in j1..j2 {
-                    let u = out[j];
-                    let v = out[j + t] * s;
-                    out[j] = (u + v) % q;
-                    out[j + t] = (u - v + q) % q;
-                }
-            }
-
-            m *= 2;
-        }
-
-        let mut ordered = vec![0; n];
-
-        for i in 0..n {
-            ordered[i] = out[<i32 as TryInto<usize>>::try_into(NegacyclicRing::bit_reverse(i.try_into().unwrap(), k.try_into().unwrap())).unwrap()];
-            ordered[i] %= self.modulus;
-            ordered[i] += self.modulus;
-            ordered[i] %= self.modulus;
-        }
-
-        Some(ordered)
+        Some(self.context()?.ntt(val))
     }
 
     /// Inverse number theoretic transform in the negacyclic ring.
     ///
+    /// Uses the shared NTT context of the ring, see [`NegacyclicRing::context`].
+    ///
     /// ```
     ///# use partav2::ring::*;
     /// assert_eq!(
@@ -242,94 +374,211 @@ impl NegacyclicRing {
     /// );
     /// ```
     pub fn intt(&self, val: Vec<i32>) -> Option<Vec<i32>> {
-        let rootunity = self.inverse(self.primitive2nthunity().unwrap()).unwrap();
-        let inverse = self.inverse(self.size()).unwrap();
-        let k: usize = self.exponent.try_into().unwrap();
-        let q = self.modulus;
-        let n: usize = self.size().try_into().unwrap();
+        Some(self.context()?.intt(val))
+    }
 
-        let mut out = vec![0; n];
+    /// Multiplication of polynomials over a negacyclic ring.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// assert_eq!(
+    ///     NegacyclicRing::new(3, 7681).mul(vec![1, 1], vec![0, 1]),
+    ///     Some(vec![0, 1, 1, 0, 0, 0, 0, 0])
+    /// );
+    /// ```
+    pub fn mul(&self, a: Vec<i32>, b: Vec<i32>) -> Option<Vec<i32>> {
+        Some(self.context()?.mul(a, b))
+    }
+
+    /// Schoolbook multiplication of polynomials over a negacyclic ring, for any modulus.
+    ///
+    /// Takes O(n^2) time, so it is a reference for the faster multiplications.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// assert_eq!(
+    ///     NegacyclicRing::new(2, 7680).mul_schoolbook(vec![1, 1, 0, 1], vec![0, 1]),
+    ///     vec![7679, 1, 1, 0] // X^4 = -1
+    /// );
+    /// ```
+    pub fn mul_schoolbook(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
+        let a = self.reduced(a);
+        let b = self.reduced(b);
+        let n = a.len();
 
+        let mut out = vec![0; n];
         for i in 0..n {
-            out[i] = val[<i32 as TryInto<usize>>::try_into(NegacyclicRing::bit_reverse(i.try_into().unwrap(), k.try_into().unwrap())).unwrap()] % q;
+            for j in 0..n {
+                let product = self.mul_mod(a[i], b[j]);
+
+                // X^n = -1, so products which wrap around are subtracted
+                if i + j < n {
+                    out[i + j] = self.add_mod(out[i + j], product);
+                } else {
+                    out[i + j - n] = self.sub_mod(out[i + j - n], product);
+                }
+            }
         }
 
-        let mut t: usize = 1;
-        let mut m: usize = n;
-        while m > 1 {
-            let mut j1 = 0;
-            let h = m / 2;
-
-            for i in 0..h {
-                let j2 = j1 + t;
-                let s = self.power(rootunity, NegacyclicRing::bit_reverse((h + i).try_into().unwrap(), k.try_into().unwrap()));
-
-                for j in j1..j2 {
-                    let u = out[j];
-                    let v = out[j + t];
-                    out[j] = (u + v) % q;
-                    out[j + t] = ((u - v) * s + q) % q;
-                }
+        out
+    }
+
+    /// Karatsuba multiplication of polynomials over a negacyclic ring, for any modulus.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(8, 3329); // 3329 has no 512th root of unity
+    /// let (a, b) = (ring.rand(), ring.rand());
+    ///
+    /// assert_eq!(ring.mul(a.clone(), b.clone()), None);
+    /// assert_eq!(ring.mul_karatsuba(a.clone(), b.clone()), ring.mul_schoolbook(a, b));
+    /// ```
+    pub fn mul_karatsuba(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
+        let a = self.reduced(a);
+        let b = self.reduced(b);
+        let n = a.len();
+
+        let product = self.karatsuba(&a, &b);
+
+        (0..n).map(|i| self.sub_mod(product[i], product[i + n])).collect()
+    }
 
-                j1 += t * 2;
+    /// The full product of two reduced polynomials of the same power of two length, with 2 len
+    /// coefficients, the last of which is zero.
+    fn karatsuba(&self, a: &[i32], b: &[i32]) -> Vec<i32> {
+        let n = a.len();
+        let mut out = vec![0; 2 * n];
+
+        // Below this, the bookkeeping costs more than the multiplications it saves
+        if n <= 16 {
+            for i in 0..n {
+                for j in 0..n {
+                    out[i + j] = self.add_mod(out[i + j], self.mul_mod(a[i], b[j]));
+                }
             }
 
-            t *= 2;
-            m /= 2;
+            return out;
         }
 
+        let h = n / 2;
+        let (a0, a1) = a.split_at(h);
+        let (b0, b1) = b.split_at(h);
+
+        let low = self.karatsuba(a0, b0);
+        let high = self.karatsuba(a1, b1);
+
+        let a_sum: Vec<i32> = a0.iter().zip(a1).map(|(&x, &y)| self.add_mod(x, y)).collect();
+        let b_sum: Vec<i32> = b0.iter().zip(b1).map(|(&x, &y)| self.add_mod(x, y)).collect();
+
+        // (a0 + a1)(b0 + b1) - a0 b0 - a1 b1 = a0 b1 + a1 b0
+        let middle = self.karatsuba(&a_sum, &b_sum);
+
         for i in 0..n {
-            out[i] *= inverse;
-            out[i] %= self.modulus;
-            out[i] += self.modulus;
-            out[i] %= self.modulus;
+            out[i] = self.add_mod(out[i], low[i]);
+            out[i + n] = self.add_mod(out[i + n], high[i]);
+            out[i + h] = self.add_mod(out[i + h], self.sub_mod(self.sub_mod(middle[i], low[i]), high[i]));
         }
 
-        Some(out)
+        out
     }
 
-    /// Multiplication of polynomials over a negacyclic ring.
+    /// Multiplication of a polynomial by one already in ntt-space, such as a key reused for many
+    /// products, giving the product in coefficient space.
     ///
     /// ```
     ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
     /// assert_eq!(
-    ///     NegacyclicRing::new(3, 7681).mul(vec![1, 1], vec![0, 1]),
-    ///     Some(vec![0, 1, 1, 0, 0, 0, 0, 0])
+    ///     ring.mul_by_ntt(vec![1, 1], ring.ntt(vec![0, 1]).unwrap()),
+    ///     ring.mul(vec![1, 1], vec![0, 1])
     /// );
     /// ```
-    pub fn mul(&self, a: Vec<i32>, b: Vec<i32>) -> Option<Vec<i32>> {
-        let antt = self.ntt(a).unwrap();
-        let bntt = self.ntt(b).unwrap();
-
-        let mut outntt = vec![0; self.size().try_into().unwrap()];
+    pub fn mul_by_ntt(&self, a: Vec<i32>, b: Vec<i32>) -> Option<Vec<i32>> {
+        let context = self.context()?;
 
-        for i in 0..outntt.len() {
-            outntt[i] = antt[i] * bntt[i];
-        }
+        Some(context.intt(self.mul_ntt(context.ntt(a), b)))
+    }
 
-        self.intt(outntt)
+    /// Multiplication of polynomials which are both in ntt-space, giving the product in ntt-space.
+    ///
+    /// The product is pointwise, so no transform is needed.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// assert_eq!(
+    ///     ring.intt(ring.mul_ntt(ring.ntt(vec![1, 1]).unwrap(), ring.ntt(vec![0, 1]).unwrap())),
+    ///     ring.mul(vec![1, 1], vec![0, 1])
+    /// );
+    /// ```
+    pub fn mul_ntt(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
+        self.combine(a, b, |a, b| self.mul_mod(a, b))
     }
 
     /// Addition of polynomials over a negacyclic ring.
     ///
+    /// Addition is coefficientwise, so both may be in coefficient space or both in ntt-space.
+    ///
     /// ```
     ///# use partav2::ring::*;
     /// assert_eq!(
     ///     NegacyclicRing::new(3, 7681).add(vec![1, 1], vec![0, 1]),
-    ///     Some(vec![1, 2, 0, 0, 0, 0, 0, 0])
+    ///     vec![1, 2, 0, 0, 0, 0, 0, 0]
     /// );
     /// ```
-    pub fn add(&self, a: Vec<i32>, b: Vec<i32>) -> Option<Vec<i32>> {
-        let antt = self.ntt(a).unwrap();
-        let bntt = self.ntt(b).unwrap();
+    pub fn add(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
+        self.combine(a, b, |a, b| self.add_mod(a, b))
+    }
 
-        let mut outntt = vec![0; self.size().try_into().unwrap()];
+    /// Subtraction of polynomials over a negacyclic ring, in either space.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// assert_eq!(
+    ///     NegacyclicRing::new(3, 7681).sub(vec![1, 1], vec![0, 2]),
+    ///     vec![1, 7680, 0, 0, 0, 0, 0, 0]
+    /// );
+    /// ```
+    pub fn sub(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
+        self.combine(a, b, |a, b| self.sub_mod(a, b))
+    }
 
-        for i in 0..outntt.len() {
-            outntt[i] = antt[i] + bntt[i];
-        }
+    /// Negation of a polynomial over a negacyclic ring, in either space.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// assert_eq!(
+    ///     NegacyclicRing::new(2, 7681).neg(vec![1, 0, -2]),
+    ///     vec![7680, 0, 2, 0]
+    /// );
+    /// ```
+    pub fn neg(&self, a: Vec<i32>) -> Vec<i32> {
+        self.sub(vec![], a)
+    }
 
-        self.intt(outntt)
+    /// Multiplication of a polynomial by a scalar, in either space.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// assert_eq!(
+    ///     NegacyclicRing::new(2, 7681).scalar_mul(vec![1, 2, 3], -2),
+    ///     vec![7679, 7677, 7675, 0]
+    /// );
+    /// ```
+    pub fn scalar_mul(&self, a: Vec<i32>, k: i32) -> Vec<i32> {
+        let k = self.reduce(k);
+
+        self.reduced(a).into_iter().map(|v| self.mul_mod(v, k)).collect()
+    }
+
+    /// Pads both polynomials, and combines their reduced coefficients pairwise.
+    fn combine(&self, a: Vec<i32>, b: Vec<i32>, f: impl Fn(i32, i32) -> i32) -> Vec<i32> {
+        self.reduced(a).into_iter().zip(self.reduced(b)).map(|(a, b)| f(a, b)).collect()
+    }
+
+    /// Pads a polynomial, and reduces its coefficients into [0, q).
+    fn reduced(&self, a: Vec<i32>) -> Vec<i32> {
+        self.pad(a).into_iter().map(|v| self.reduce(v)).collect()
     }
 
     /// Gaussion polynomial sampling over a negacyclic ring.
@@ -339,24 +588,63 @@ impl NegacyclicRing {
     /// NegacyclicRing::new(3, 7681).sample();
     /// ```
     pub fn sample(&self) -> Vec<i32> {
-        let mut out = vec![0; self.size().try_into().unwrap()];
+        self.sample_with_rng(&mut Rand::new())
+    }
+
+    /// Gaussion polynomial sampling over a negacyclic ring, from the given generator.
+    ///
+    /// Coefficients follow the discrete Gaussian with a standard deviation of 8 / sqrt(2 pi).
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::rand::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// assert_eq!(ring.sample_with_rng(&mut Rand::from_seed([1; 32])), ring.sample_with_rng(&mut Rand::from_seed([1; 32])));
+    /// ```
+    pub fn sample_with_rng(&self, rng: &mut impl CryptoRng) -> Vec<i32> {
+        self.sample_gaussian(DiscreteGaussian::standard(), rng)
+    }
 
-        let mut rnd = Rand::new();
+    /// Centered binomial polynomial sampling over a negacyclic ring.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// NegacyclicRing::new(3, 7681).sample_cbd(2);
+    /// ```
+    pub fn sample_cbd(&self, eta: u32) -> Vec<i32> {
+        self.sample_cbd_with_rng(eta, &mut Rand::new())
+    }
 
-        let sample_bound = 8.0 / (2.0 * std::f64::consts::PI).sqrt();
+    /// Centered binomial polynomial sampling over a negacyclic ring, from the given generator.
+    ///
+    /// Each coefficient is the difference of the sums of two sets of eta random bits, so lies in
+    /// [-eta, eta] with a variance of eta / 2. Eta must be between 1 and 16.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::rand::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// let poly = ring.sample_cbd_with_rng(2, &mut Rand::new());
+    ///
+    /// assert!(poly.iter().all(|v| *v <= 2 || *v >= 7681 - 2));
+    /// ```
+    pub fn sample_cbd_with_rng(&self, eta: u32, rng: &mut impl CryptoRng) -> Vec<i32> {
+        assert!((1..=16).contains(&eta), "sample_cbd_with_rng: eta: Must be between 1 and 16.");
 
-        for i in 0..self.size().try_into().unwrap() {
-            let rnd1 = ((rnd.next() % 1000000000) as f64) / 1000000000.0;
-            let rnd2 = ((rnd.next() % 1000000000) as f64) / 1000000000.0;
+        let eta = eta as usize;
+        let n = self.size() as usize;
 
-            out[i] = ((-2.0 * rnd1.ln()).sqrt() * (2.0 * std::f64::consts::PI * rnd2).cos() * sample_bound) as i32;
+        let mut bytes = vec![0u8; (2 * n * eta).div_ceil(8)];
+        rng.fill_bytes(&mut bytes);
 
-            out[i] %= self.modulus;
-            out[i] += self.modulus;
-            out[i] %= self.modulus;
-        }
+        let bit = |i: usize| ((bytes[i / 8] >> (i % 8)) & 1) as i32;
 
-        out
+        (0..n).map(|i| {
+            let a: i32 = (0..eta).map(|j| bit(2 * i * eta + j)).sum();
+            let b: i32 = (0..eta).map(|j| bit(2 * i * eta + eta + j)).sum();
+
+            self.reduce(a - b)
+        }).collect()
     }
 
     /// Regular polynomial sampling with respect to a negacyclic ring.
@@ -366,20 +654,296 @@ impl NegacyclicRing {
     /// NegacyclicRing::new(3, 7681).rand();
     /// ```
     pub fn rand(&self) -> Vec<i32> {
-        let mut out = vec![0; self.size().try_into().unwrap()];
+        self.rand_with_rng(&mut Rand::new())
+    }
+
+    /// Regular polynomial sampling with respect to a negacyclic ring, from the given generator.
+    ///
+    /// Coefficients are uniform in [0, q), so the polynomial is uniform in either the coefficient
+    /// or the ntt domain, and can be used directly as an ntt polynomial.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::rand::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// assert_eq!(ring.rand_with_rng(&mut Rand::from_seed([1; 32])), ring.rand_with_rng(&mut Rand::from_seed([1; 32])));
+    /// ```
+    pub fn rand_with_rng(&self, rng: &mut impl CryptoRng) -> Vec<i32> {
+        self.rand_from(|bytes| rng.fill_bytes(bytes))
+    }
+
+    /// Regular polynomial sampling with respect to a negacyclic ring, from an extendable output function.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::sha3::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    ///
+    /// let mut xof = Shake::new(128);
+    /// xof.absorb(b"seed");
+    /// assert!(ring.rand_from_xof(&mut xof).iter().all(|v| (0..7681).contains(v)));
+    /// ```
+    pub fn rand_from_xof(&self, xof: &mut Shake) -> Vec<i32> {
+        self.rand_from(|bytes| xof.squeeze(bytes))
+    }
+
+    /// Uniform polynomial expanded from a 32 byte seed with SHAKE128.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// assert_eq!(ring.rand_from_seed(&[1; 32]), ring.rand_from_seed(&[1; 32]));
+    /// ```
+    pub fn rand_from_seed(&self, seed: &[u8; 32]) -> Vec<i32> {
+        let mut xof = Shake::new(128);
+        xof.absorb(seed);
+
+        self.rand_from_xof(&mut xof)
+    }
+
+    /// Rejection sampling of uniform coefficients, from a source of random bytes.
+    ///
+    /// Candidates are masked to the bit length of the modulus and kept if they are below it,
+    /// so at least half are kept, and those are free of modulo bias.
+    fn rand_from(&self, mut fill: impl FnMut(&mut [u8])) -> Vec<i32> {
+        let bits = self.coefficient_bits();
+        let mask = ((1u64 << bits) - 1) as u32;
+        let width = bits.div_ceil(8) as usize;
+
+        let mut out = Vec::with_capacity(self.size() as usize);
+        let mut candidate = [0u8; 4];
+
+        while out.len() < self.size() as usize {
+            fill(&mut candidate[..width]);
+
+            let v = u32::from_le_bytes(candidate) & mask;
+            if v < self.modulus as u32 {
+                out.push(v as i32);
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_chi_squared() {
+        const BINS: usize = 64;
+
+        let ring = NegacyclicRing::new(9, 25601);
+        let mut rng = Rand::from_seed([6; 32]);
+
+        let mut counts = [0f64; BINS];
+        for _ in 0..400 {
+            for v in ring.rand_with_rng(&mut rng) {
+                assert!((0..ring.modulus).contains(&v));
+                counts[v as usize * BINS / ring.modulus as usize] += 1.0;
+            }
+        }
+
+        // The exact share of [0, q) in every bin, as the modulus does not divide evenly
+        let samples = (400 * ring.size()) as f64;
+        let mut widths = [0f64; BINS];
+        for v in 0..ring.modulus as usize {
+            widths[v * BINS / ring.modulus as usize] += 1.0;
+        }
+
+        let statistic: f64 = counts.iter().zip(widths).map(|(o, w)| {
+            let e = w / ring.modulus as f64 * samples;
+            (o - e).powi(2) / e
+        }).sum();
+
+        // The critical value of chi-squared with 63 degrees of freedom at p = 0.001
+        assert!(statistic < 103.4, "chi-squared {}", statistic);
+    }
+
+    #[test]
+    fn uniform_from_xof_is_deterministic() {
+        let ring = NegacyclicRing::new(9, 25601);
+
+        let xof = |seed: &[u8]| {
+            let mut xof = Shake::new(128);
+            xof.absorb(seed);
+            xof
+        };
+
+        assert_eq!(ring.rand_from_xof(&mut xof(b"a")), ring.rand_from_xof(&mut xof(b"a")));
+        assert_ne!(ring.rand_from_xof(&mut xof(b"a")), ring.rand_from_xof(&mut xof(b"b")));
+
+        // Every modulus width, including one that needs all 4 bytes of a candidate
+        for q in [2, 257, 7681, 65537, 1 << 30] {
+            assert!(NegacyclicRing::new(4, q).rand_from_xof(&mut xof(b"c")).iter().all(|v| (0..q).contains(v)));
+        }
+    }
+
+    /// The largest prime below 2^31 that is 1 mod 2048
+    const LARGE: i32 = 2147473409;
+
+    /// Negacyclic product by the definition, in i128 so nothing can overflow
+    fn schoolbook(ring: &NegacyclicRing, a: &[i32], b: &[i32]) -> Vec<i32> {
+        let n = a.len();
+        let q = ring.modulus as i128;
+
+        let mut out = vec![0i128; n];
+        for i in 0..n {
+            for j in 0..n {
+                let product = a[i] as i128 * b[j] as i128;
+                if i + j < n {
+                    out[i + j] += product;
+                }
+                else {
+                    out[i + j - n] -= product;
+                }
+            }
+        }
+
+        out.into_iter().map(|v| v.rem_euclid(q) as i32).collect()
+    }
+
+    #[test]
+    fn roots_match_exhaustive_search() {
+        for (exponent, q) in [(0, 7681), (1, 17), (2, 7681), (3, 97), (4, 257), (6, 7681)] {
+            let ring = NegacyclicRing::new(exponent, q);
+            let n = ring.size();
+
+            let nthunity = (1..q).find(|&z| ring.power(z, n) == 1 && (1..n).all(|k| ring.power(z, k) != 1));
+            let unity = nthunity.and_then(|r| (1..q).find(|&z| ring.power(z, 2) == r && ring.power(z, n) == q - 1));
+
+            assert_eq!(ring.primitiventhunity(), nthunity, "{:?}", ring);
+            assert_eq!(ring.primitive2nthunity(), unity, "{:?}", ring);
+        }
+
+        assert!(NegacyclicRing::new(5, 97).primitiventhunity().is_some());
+        assert_eq!(NegacyclicRing::new(5, 97).primitive2nthunity(), None);
+        assert_eq!(NegacyclicRing::new(30, 7681).primitive2nthunity(), None);
+    }
+
+    #[test]
+    fn large_modulus_arithmetic() {
+        let ring = NegacyclicRing::new(9, LARGE);
+        let q = LARGE as i128;
+
+        let mut rng = Rand::from_seed([7; 32]);
+        for _ in 0..1000 {
+            let (a, b) = (rng.next_i32().rem_euclid(LARGE), rng.next_i32().rem_euclid(LARGE));
+
+            assert_eq!(ring.mul_mod(a, b) as i128, a as i128 * b as i128 % q);
+            assert_eq!(ring.add_mod(a, b) as i128, (a as i128 + b as i128) % q);
+            assert_eq!(ring.sub_mod(a, b) as i128, (a as i128 - b as i128).rem_euclid(q));
+
+            if a != 0 {
+                assert_eq!(ring.mul_mod(a, ring.inverse(a).unwrap()), 1);
+            }
+        }
+
+        // Fermat's little theorem
+        assert_eq!(ring.power(123456789, LARGE - 1), 1);
+        assert_eq!(ring.power(LARGE - 1, 3), LARGE - 1);
+    }
+
+    #[test]
+    fn large_modulus_transform() {
+        let ring = NegacyclicRing::new(9, LARGE);
+        let mut rng = Rand::from_seed([8; 32]);
+
+        let a = ring.rand_with_rng(&mut rng);
+        let b = ring.rand_with_rng(&mut rng);
+
+        assert_eq!(ring.intt(ring.ntt(a.clone()).unwrap()).unwrap(), a);
+        assert_eq!(ring.mul(a.clone(), b.clone()).unwrap(), schoolbook(&ring, &a, &b));
+
+        let sum: Vec<i32> = a.iter().zip(&b).map(|(a, b)| ((*a as i64 + *b as i64) % LARGE as i64) as i32).collect();
+        assert_eq!(ring.add(a, b), sum);
+    }
+
+    #[test]
+    fn linear_operations_commute_with_the_transform() {
+        let ring = NegacyclicRing::new(8, 7681);
+        let mut rng = Rand::from_seed([10; 32]);
+
+        let a = ring.rand_with_rng(&mut rng);
+        let b = ring.rand_with_rng(&mut rng);
+        let ntt = |x: Vec<i32>| ring.ntt(x).unwrap();
+
+        assert_eq!(ring.add(ntt(a.clone()), ntt(b.clone())), ntt(ring.add(a.clone(), b.clone())));
+        assert_eq!(ring.sub(ntt(a.clone()), ntt(b.clone())), ntt(ring.sub(a.clone(), b.clone())));
+        assert_eq!(ring.neg(ntt(a.clone())), ntt(ring.neg(a.clone())));
+        assert_eq!(ring.scalar_mul(ntt(a.clone()), 1234), ntt(ring.scalar_mul(a.clone(), 1234)));
+
+        assert_eq!(ring.add(ring.sub(a.clone(), b.clone()), b.clone()), a);
+        assert_eq!(ring.add(ring.neg(a.clone()), a.clone()), vec![0; 256]);
+
+        let product = ring.mul(a.clone(), b.clone());
+        assert_eq!(ring.mul_by_ntt(a.clone(), ntt(b.clone())), product);
+        assert_eq!(ring.intt(ring.mul_ntt(ntt(a), ntt(b))), product);
+    }
+
+    #[test]
+    fn multipliers_agree() {
+        let mut rng = Rand::from_seed([11; 32]);
+
+        // Rings with and without a number theoretic transform, including even and large moduli
+        for (exponent, q) in [(0, 7681), (3, 17), (5, 7681), (8, 7681), (9, 25601), (10, 12289), (8, 3329), (9, 25600), (6, 1 << 30), (7, 65537), (9, LARGE), (6, i32::MAX)] {
+            let ring = NegacyclicRing::new(exponent, q);
 
-        let mut rnd = Rand::new();
+            for _ in 0..5 {
+                // Unreduced and short inputs, which every multiplier reduces and pads
+                let a: Vec<i32> = (0..ring.size()).map(|_| rng.next_i32()).collect();
+                let b: Vec<i32> = (0..ring.size() / 2 + 1).map(|_| rng.next_i32()).collect();
 
-        for i in 0..self.size().try_into().unwrap() {
-            let rnd1 = rnd.next();
+                let expected = schoolbook(&ring, &ring.reduced(a.clone()), &ring.reduced(b.clone()));
 
-            out[i] = rnd1;
+                assert_eq!(ring.mul_schoolbook(a.clone(), b.clone()), expected, "schoolbook, n = {}, q = {}", ring.size(), q);
+                assert_eq!(ring.mul_karatsuba(a.clone(), b.clone()), expected, "karatsuba, n = {}, q = {}", ring.size(), q);
 
-            out[i] %= self.modulus;
-            out[i] += self.modulus;
-            out[i] %= self.modulus;
+                if let Some(product) = ring.mul(a, b) {
+                    assert_eq!(product, expected, "ntt, n = {}, q = {}", ring.size(), q);
+                }
+            }
         }
+    }
+
+    #[test]
+    fn reductions_agree_with_division() {
+        let mut rng = Rand::from_seed([9; 32]);
+
+        for (exponent, q) in [(9, 25601), (10, 12289), (9, LARGE), (4, 1 << 30)] {
+            let naive = NegacyclicRing::new(exponent, q).with_reduction(Reduction::Division);
+            let reductions: &[Reduction] = if q % 2 == 1 { &[Reduction::Barrett, Reduction::Montgomery] } else { &[Reduction::Barrett] };
+
+            for &reduction in reductions {
+                let ring = naive.with_reduction(reduction);
+
+                for _ in 0..10000 {
+                    let (a, b) = (rng.next_i32().rem_euclid(q), rng.next_i32().rem_euclid(q));
+
+                    assert_eq!(ring.mul_mod(a, b), naive.mul_mod(a, b));
+                    assert_eq!(ring.add_mod(a, b), naive.add_mod(a, b));
+                    assert_eq!(ring.sub_mod(a, b), naive.sub_mod(a, b));
+                }
+                for (a, b) in [(0, 0), (q - 1, q - 1), (0, q - 1), (q - 1, 0)] {
+                    assert_eq!((ring.mul_mod(a, b), ring.add_mod(a, b), ring.sub_mod(a, b)), (naive.mul_mod(a, b), naive.add_mod(a, b), naive.sub_mod(a, b)));
+                }
+
+                if naive.context().is_some() {
+                    let a = naive.rand_with_rng(&mut rng);
+                    let b = naive.rand_with_rng(&mut rng);
+
+                    assert_eq!(ring.ntt(a.clone()), naive.ntt(a.clone()));
+                    assert_eq!(ring.intt(a.clone()), naive.intt(a.clone()));
+                    assert_eq!(ring.mul(a.clone(), b.clone()), naive.mul(a, b));
+                }
+            }
+        }
+    }
 
-        self.ntt(self.intt(out).unwrap()).unwrap()
+    #[test]
+    #[should_panic]
+    fn montgomery_needs_an_odd_modulus() {
+        NegacyclicRing::new(4, 1 << 30).with_reduction(Reduction::Montgomery);
     }
 }
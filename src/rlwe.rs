@@ -1,4 +1,9 @@
 use crate::ring::NegacyclicRing;
+use crate::poly::{NttPoly, Poly, expect_ring};
+use crate::error::Error;
+use crate::rand::Rand;
+use crate::gaussian::SIGMA;
+use rand::CryptoRng;
 
 impl NegacyclicRing {
     /// Transforms the input polynomial to depth 1, and acts as the error correction for RLWE.
@@ -6,26 +11,27 @@ impl NegacyclicRing {
     /// ```
     ///# use partav2::ring::*;
     ///# use partav2::rlwe::*;
+    ///# use partav2::poly::*;
+    /// let ring = NegacyclicRing::new(2, 7681);
     /// assert_eq!(
-    ///     NegacyclicRing::new(2, 7681).signal(vec![2, 3, 4096, 7661]),
-    ///     vec![1, 1, 0, 1]
+    ///     ring.signal(&Poly::new(ring, vec![2, 3, 4096, 7661]).unwrap()).coefficients(),
+    ///     &[1, 1, 0, 1]
     /// );
     /// ```
-    pub fn signal(&self, poly: Vec<i32>) -> Vec<i32> {
+    pub fn signal(&self, poly: &Poly) -> Poly {
+        expect_ring(*self, poly.ring(), "NegacyclicRing::signal", "poly");
+
         let mut out = vec![0; self.size().try_into().unwrap()];
 
         let min_bound = self.modulus / 4;
-        let max_bound = 3 * self.modulus / 4;
-        for i in 0..poly.len() {
-            if poly[i] < min_bound || poly[i] > max_bound {
-                out[i] = 1;
-            }
-            else {
-                out[i] = 0;
-            }
+        let max_bound = (3 * self.modulus as i64 / 4) as i32;
+
+        // The sign bits of v - min_bound and max_bound - v, so the comparison does not branch on v
+        for (o, v) in out.iter_mut().zip(poly.coefficients()) {
+            *o = (((v - min_bound) >> 31) | ((max_bound - v) >> 31)) & 1;
         }
 
-        out
+        Poly::new(*self, out).unwrap()
     }
 
     /// Combines the ntt-space key with the ntt-space signal to generate a shared private key.
@@ -33,187 +39,366 @@ impl NegacyclicRing {
     /// ```
     ///# use partav2::ring::*;
     ///# use partav2::rlwe::*;
+    ///# use partav2::poly::*;
     /// let ring = NegacyclicRing::new(2, 7681);
+    /// let a = Poly::new(ring, vec![4, 1, 2, 3]).unwrap().to_ntt().unwrap();
+    /// let b = Poly::new(ring, vec![1, 2, 3, 4]).unwrap().to_ntt().unwrap();
     /// assert_eq!(
-    ///     ring.modulo2(ring.ntt(vec![4, 1, 2, 3]).unwrap(), ring.ntt(vec![1, 2, 3, 4]).unwrap()),
-    ///     vec![0, 0, 1, 1]
+    ///     ring.modulo2(&a, &b).coefficients(),
+    ///     &[0, 0, 1, 1]
     /// );
     /// ```
-    pub fn modulo2(&self, a: Vec<i32>, b: Vec<i32>) -> Vec<i32> {
-        // let mut out = self.add(a, self.mul(b, vec![(self.modulus - 1) / 2]).unwrap()).unwrap();
-        let mut out = self.intt(a.into_iter().zip(b).map(|(a, b)| a + b * (self.modulus - 1) / 2).collect()).unwrap();
+    pub fn modulo2(&self, a: &NttPoly, b: &NttPoly) -> Poly {
+        expect_ring(*self, a.ring(), "NegacyclicRing::modulo2", "a");
+        expect_ring(*self, b.ring(), "NegacyclicRing::modulo2", "b");
+
+        let mut out = Poly::from_ntt(&(a + &(b * ((self.modulus - 1) / 2)))).into_coefficients();
 
         for v in &mut out {
-            *v %= 2;
+            *v &= 1;
         }
 
-        out
+        Poly::new(*self, out).unwrap()
     }
 }
 
 pub struct RLWE;
 
+/// The size in bytes of the seed of the public polynomial a
+pub const SEED_SIZE: usize = 32;
+
 /// A public keypair for the RLWE key exchange, in ntt-space.
+///
+/// Only the seed of a is sent, and a is derived from it with SHAKE128, so neither side can choose a.
 #[derive(Clone, Debug, PartialEq)]
 pub struct PublicKeypair {
-    a: Vec<i32>,
-    p: Vec<i32>
+    seed: [u8; SEED_SIZE],
+    p: NttPoly
 }
 
 /// A private keypair for the RLWE key exchange, in ntt-space.
 #[derive(Clone, Debug, PartialEq)]
 pub struct PrivateKeypair {
-    a: Vec<i32>,
-    s: Vec<i32>
+    s: NttPoly
 }
 
-pub trait KeyshareRLWE {
-    fn generate(ring: NegacyclicRing) -> (PrivateKeypair, PublicKeypair);
-    fn respond(ring: NegacyclicRing, key: PublicKeypair) -> (Vec<i32>, PublicKeypair);
-    fn parse(ring: NegacyclicRing, private: PrivateKeypair, public: PublicKeypair) -> Vec<i32>;
+/// The response keypair for the RLWE key exchange, in ntt-space, and the reconciliation signal as bits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseKeypair {
+    w: Poly,
+    p: NttPoly
 }
 
-pub trait TransmuteBytes {
-    fn from_bytes(bytes: Vec<u8>) -> Self;
-    fn to_bytes(self) -> Vec<u8>;
+/// The distribution of the secret and error polynomials of the key exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Noise {
+    /// The discrete Gaussian with a standard deviation of 8 / sqrt(2 pi)
+    Gaussian,
+    /// The centered binomial distribution with parameter eta
+    Binomial(u32)
 }
 
-impl TransmuteBytes for PublicKeypair {
-    fn from_bytes(bytes: Vec<u8>) -> Self {
-        let size = bytes.len() / size_of::<u32>() / 2;
-        assert_eq!(size * size_of::<u32>() * 2, bytes.len());
+impl Noise {
+    /// Samples a noise polynomial over the ring, from the given generator.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::rlwe::*;
+    ///# use partav2::rand::*;
+    /// let ring = NegacyclicRing::new(3, 7681);
+    /// assert_eq!(Noise::Binomial(4).sample(ring, &mut Rand::new()).len(), 8);
+    /// ```
+    pub fn sample(&self, ring: NegacyclicRing, rng: &mut impl CryptoRng) -> Vec<i32> {
+        match self {
+            Noise::Gaussian => ring.sample_with_rng(rng),
+            Noise::Binomial(eta) => ring.sample_cbd_with_rng(*eta, rng)
+        }
+    }
 
-        let mut out1 = vec![0; size];
-        let mut out2 = vec![0; size];
+    /// Samples a noise polynomial over the ring, in ntt-space.
+    fn sample_ntt(&self, ring: NegacyclicRing, rng: &mut impl CryptoRng) -> NttPoly {
+        Poly::new(ring, self.sample(ring, rng)).unwrap().to_ntt().unwrap()
+    }
 
-        for i in 0..size {
-            out1[i] = i32::from_le_bytes(bytes[i * 4 .. i * 4 + 4].try_into().unwrap());
-            out2[i] = i32::from_le_bytes(bytes[size * 4 + i * 4 .. size * 4 + i * 4 + 4].try_into().unwrap());
+    /// The variance of a single noise coefficient.
+    ///
+    /// ```
+    ///# use partav2::rlwe::*;
+    /// assert_eq!(Noise::Binomial(4).variance(), 2.0);
+    /// ```
+    pub fn variance(&self) -> f64 {
+        match self {
+            Noise::Gaussian => SIGMA * SIGMA,
+            Noise::Binomial(eta) => *eta as f64 / 2.0
         }
+    }
+}
+
+/// The steps of the key exchange, each drawing randomness from an explicit generator,
+/// or from a freshly seeded one.
+pub trait KeyshareRLWE {
+    fn generate_with_rng(ring: NegacyclicRing, noise: Noise, rng: &mut impl CryptoRng) -> (PrivateKeypair, PublicKeypair);
+    fn respond_with_rng(ring: NegacyclicRing, noise: Noise, key: PublicKeypair, rng: &mut impl CryptoRng) -> (Vec<i32>, ResponseKeypair);
+    fn parse_with_rng(ring: NegacyclicRing, noise: Noise, private: PrivateKeypair, public: ResponseKeypair, rng: &mut impl CryptoRng) -> Vec<i32>;
+
+    fn generate(ring: NegacyclicRing, noise: Noise) -> (PrivateKeypair, PublicKeypair) {
+        Self::generate_with_rng(ring, noise, &mut Rand::new())
+    }
+    fn respond(ring: NegacyclicRing, noise: Noise, key: PublicKeypair) -> (Vec<i32>, ResponseKeypair) {
+        Self::respond_with_rng(ring, noise, key, &mut Rand::new())
+    }
+    fn parse(ring: NegacyclicRing, noise: Noise, private: PrivateKeypair, public: ResponseKeypair) -> Vec<i32> {
+        Self::parse_with_rng(ring, noise, private, public, &mut Rand::new())
+    }
+}
+
+/// Conversion of keypairs to and from their packed encoding in a ring.
+pub trait TransmuteBytes: Sized {
+    fn from_bytes(ring: &NegacyclicRing, bytes: Vec<u8>) -> Result<Self, Error>;
+    fn to_bytes(self, ring: &NegacyclicRing) -> Vec<u8>;
+}
 
-        PublicKeypair {
-            a: out1,
-            p: out2
+/// Packs a polynomial with the given number of bits per coefficient, least significant bit first.
+///
+/// ```
+///# use partav2::rlwe::*;
+/// assert_eq!(pack_polynomial(&[1, 0, 1, 1], 1), vec![0b1101]);
+/// assert_eq!(pack_polynomial(&[0x1ff, 0x002], 9), vec![0xff, 0x05, 0x00]);
+/// ```
+pub fn pack_polynomial(poly: &[i32], bits: u32) -> Vec<u8> {
+    let mut out = vec![0u8; (poly.len() * bits as usize).div_ceil(8)];
+
+    for (i, v) in poly.iter().enumerate() {
+        for j in 0..bits as usize {
+            let bit = i * bits as usize + j;
+            out[bit / 8] |= (((v >> j) & 1) as u8) << (bit % 8);
         }
     }
-    fn to_bytes(self) -> Vec<u8> {
-        let mut bytes = vec![];
 
-        for i in 0..self.a.len() {
-            bytes.extend(self.a[i].to_le_bytes());
+    out
+}
+
+/// Unpacks a polynomial of size coefficients with the given number of bits each.
+///
+/// Fails if the length is wrong, a coefficient is not below the bound, or the padding is not zero.
+///
+/// ```
+///# use partav2::rlwe::*;
+///# use partav2::error::Error;
+/// assert_eq!(unpack_polynomial(&[0xff, 0x05, 0x00], 9, 2, 512), Ok(vec![0x1ff, 0x002]));
+/// assert_eq!(unpack_polynomial(&[0xff, 0x05, 0x00], 9, 2, 500), Err(Error::CoefficientOutOfRange));
+/// assert_eq!(unpack_polynomial(&[0xff, 0x05], 9, 2, 512), Err(Error::Truncated));
+/// assert_eq!(unpack_polynomial(&[0xff, 0x05, 0x00, 0x00], 9, 2, 512), Err(Error::TrailingData));
+/// assert_eq!(unpack_polynomial(&[0xff, 0x05, 0x04], 9, 2, 512), Err(Error::CoefficientOutOfRange));
+/// ```
+pub fn unpack_polynomial(bytes: &[u8], bits: u32, size: usize, bound: i32) -> Result<Vec<i32>, Error> {
+    let expected = (size * bits as usize).div_ceil(8);
+    if bytes.len() < expected {
+        return Err(Error::Truncated);
+    }
+    if bytes.len() > expected {
+        return Err(Error::TrailingData);
+    }
+
+    let bit = |i: usize| ((bytes[i / 8] >> (i % 8)) & 1) as i32;
+
+    let out: Vec<i32> = (0..size).map(|i| {
+        (0..bits as usize).map(|j| bit(i * bits as usize + j) << j).sum()
+    }).collect();
+
+    if out.iter().any(|&v| v >= bound) || (size * bits as usize..expected * 8).any(|i| bit(i) != 0) {
+        return Err(Error::CoefficientOutOfRange);
+    }
+
+    Ok(out)
+}
+
+impl NegacyclicRing {
+    /// Checks that a received polynomial belongs to the ring.
+    ///
+    /// ```
+    ///# use partav2::ring::*;
+    ///# use partav2::error::Error;
+    /// let ring = NegacyclicRing::new(1, 7681);
+    /// assert_eq!(ring.check(&[0, 7680]), Ok(()));
+    /// assert_eq!(ring.check(&[0, 7681]), Err(Error::CoefficientOutOfRange));
+    /// assert_eq!(ring.check(&[0]), Err(Error::BadHeader));
+    /// ```
+    pub fn check(&self, poly: &[i32]) -> Result<(), Error> {
+        if poly.len() != self.size() as usize {
+            return Err(Error::BadHeader);
         }
-        for i in 0..self.p.len() {
-            bytes.extend(self.p[i].to_le_bytes());
+
+        if poly.iter().any(|&v| v < 0 || v >= self.modulus) {
+            return Err(Error::CoefficientOutOfRange);
         }
 
-        bytes
+        Ok(())
     }
 }
 
-impl TransmuteBytes for PrivateKeypair {
-    fn from_bytes(bytes: Vec<u8>) -> Self {
-        let size = bytes.len() / size_of::<u32>() / 2;
-        assert_eq!(size * size_of::<u32>() * 2, bytes.len());
+impl PublicKeypair {
+    /// Checks that the polynomial of the keypair belongs to the ring.
+    pub fn check(&self, ring: &NegacyclicRing) -> Result<(), Error> {
+        ring.check(self.p.values())
+    }
+
+    /// The size in bytes of a public keypair in the ring
+    pub fn size(ring: &NegacyclicRing) -> usize {
+        SEED_SIZE + (ring.size() as usize * ring.coefficient_bits() as usize).div_ceil(8)
+    }
+}
 
-        let mut out1 = vec![0; size];
-        let mut out2 = vec![0; size];
+impl PrivateKeypair {
+    /// Checks that the polynomial of the keypair belongs to the ring.
+    pub fn check(&self, ring: &NegacyclicRing) -> Result<(), Error> {
+        ring.check(self.s.values())
+    }
+}
 
-        for i in 0..size {
-            out1[i] = i32::from_le_bytes(bytes[i * 4 .. i * 4 + 4].try_into().unwrap());
-            out2[i] = i32::from_le_bytes(bytes[size * 4 + i * 4 .. size * 4 + i * 4 + 4].try_into().unwrap());
-        }
+impl ResponseKeypair {
+    /// Checks that both polynomials of the keypair belong to the ring, and the signal is bits.
+    pub fn check(&self, ring: &NegacyclicRing) -> Result<(), Error> {
+        ring.check(self.w.coefficients())?;
+        ring.check(self.p.values())?;
 
-        PrivateKeypair {
-            a: out1,
-            s: out2
+        if self.w.coefficients().iter().any(|&v| v > 1) {
+            return Err(Error::CoefficientOutOfRange);
         }
+
+        Ok(())
     }
-    fn to_bytes(self) -> Vec<u8> {
-        let mut bytes = vec![];
 
-        for i in 0..self.a.len() {
-            bytes.extend(self.a[i].to_le_bytes());
+    /// The size in bytes of a response keypair in the ring
+    pub fn size(ring: &NegacyclicRing) -> usize {
+        (ring.size() as usize).div_ceil(8) + (ring.size() as usize * ring.coefficient_bits() as usize).div_ceil(8)
+    }
+}
+
+impl TransmuteBytes for PublicKeypair {
+    fn from_bytes(ring: &NegacyclicRing, bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() < SEED_SIZE {
+            return Err(Error::Truncated);
         }
-        for i in 0..self.s.len() {
-            bytes.extend(self.s[i].to_le_bytes());
+
+        Ok(PublicKeypair {
+            seed: bytes[..SEED_SIZE].try_into().unwrap(),
+            p: NttPoly::new(*ring, unpack_polynomial(&bytes[SEED_SIZE..], ring.coefficient_bits(), ring.size() as usize, ring.modulus)?)?
+        })
+    }
+    fn to_bytes(self, ring: &NegacyclicRing) -> Vec<u8> {
+        let mut bytes = self.seed.to_vec();
+        bytes.extend(pack_polynomial(self.p.values(), ring.coefficient_bits()));
+
+        bytes
+    }
+}
+
+impl TransmuteBytes for PrivateKeypair {
+    fn from_bytes(ring: &NegacyclicRing, bytes: Vec<u8>) -> Result<Self, Error> {
+        Ok(PrivateKeypair {
+            s: NttPoly::new(*ring, unpack_polynomial(&bytes, ring.coefficient_bits(), ring.size() as usize, ring.modulus)?)?
+        })
+    }
+    fn to_bytes(self, ring: &NegacyclicRing) -> Vec<u8> {
+        pack_polynomial(self.s.values(), ring.coefficient_bits())
+    }
+}
+
+impl TransmuteBytes for ResponseKeypair {
+    fn from_bytes(ring: &NegacyclicRing, bytes: Vec<u8>) -> Result<Self, Error> {
+        let size = ring.size() as usize;
+
+        let hint = size.div_ceil(8);
+        if bytes.len() < hint {
+            return Err(Error::Truncated);
         }
 
+        Ok(ResponseKeypair {
+            w: Poly::new(*ring, unpack_polynomial(&bytes[..hint], 1, size, 2)?)?,
+            p: NttPoly::new(*ring, unpack_polynomial(&bytes[hint..], ring.coefficient_bits(), size, ring.modulus)?)?
+        })
+    }
+    fn to_bytes(self, ring: &NegacyclicRing) -> Vec<u8> {
+        let mut bytes = pack_polynomial(self.w.coefficients(), 1);
+        bytes.extend(pack_polynomial(self.p.values(), ring.coefficient_bits()));
+
         bytes
     }
 }
 
 impl KeyshareRLWE for RLWE {
-    fn generate(ring: NegacyclicRing) -> (PrivateKeypair, PublicKeypair) {
-        let a = ring.ntt(ring.rand()).unwrap();
+    fn generate_with_rng(ring: NegacyclicRing, noise: Noise, rng: &mut impl CryptoRng) -> (PrivateKeypair, PublicKeypair) {
+        let mut seed = [0u8; SEED_SIZE];
+        rng.fill_bytes(&mut seed);
+        let a = NttPoly::new(ring, ring.rand_from_seed(&seed)).unwrap();
 
-        let s = ring.ntt(ring.sample()).unwrap();
-        let e = ring.ntt(ring.sample()).unwrap();
+        let s = noise.sample_ntt(ring, rng);
+        let e = noise.sample_ntt(ring, rng);
 
-        // let p = ring.add(ring.mul(a.clone(), s.clone()).unwrap(), ring.mul(e, vec![2]).unwrap()).unwrap();
-        let p = a.clone().into_iter().zip(s.clone()).map(|(a, b)| a * b).zip(e).map(|(z, e)| (z + e * 2) % ring.modulus).collect();
+        let p = &a * &s + e * 2;
 
         (
             PrivateKeypair {
-                a: a.clone(),
                 s
             },
             PublicKeypair {
-                a,
+                seed,
                 p
             }
         )
     }
 
-    fn respond(ring: NegacyclicRing, key: PublicKeypair) -> (Vec<i32>, PublicKeypair) {
-        let sr = ring.ntt(ring.sample()).unwrap();
-        let er = ring.ntt(ring.sample()).unwrap();
+    fn respond_with_rng(ring: NegacyclicRing, noise: Noise, key: PublicKeypair, rng: &mut impl CryptoRng) -> (Vec<i32>, ResponseKeypair) {
+        let a = NttPoly::new(ring, ring.rand_from_seed(&key.seed)).unwrap();
+
+        let sr = noise.sample_ntt(ring, rng);
+        let er = noise.sample_ntt(ring, rng);
 
-        // let pr = ring.add(ring.mul(key.a, sr.clone()).unwrap(), ring.mul(er, vec![2]).unwrap()).unwrap();
-        let pr = key.a.into_iter().zip(sr.clone()).map(|(a, b)| a * b).zip(er).map(|(z, e)| (z + e * 2) % ring.modulus).collect();
+        let pr = &a * &sr + er * 2;
 
-        let e2r = ring.ntt(ring.sample()).unwrap();
-        // let kr = ring.add(ring.mul(key.p, sr).unwrap(), ring.mul(e2r, vec![2]).unwrap()).unwrap();
-        let kr: Vec<i32> = key.p.into_iter().zip(sr).map(|(a, b)| a * b).zip(e2r).map(|(z, e)| (z + e * 2) % ring.modulus).collect();
-        let kr_raw = ring.intt(kr.clone()).unwrap();
+        let e2r = noise.sample_ntt(ring, rng);
+        let kr = key.p * &sr + e2r * 2;
 
-        let w = ring.ntt(ring.signal(kr_raw)).unwrap();
-        let skr = ring.modulo2(kr, w.clone());
+        let w = ring.signal(&Poly::from_ntt(&kr));
+        let skr = ring.modulo2(&kr, &w.to_ntt().unwrap());
 
         (
-            skr,
-            PublicKeypair {
-                a: w,
+            skr.into_coefficients(),
+            ResponseKeypair {
+                w,
                 p: pr
             }
         )
     }
 
-    fn parse(ring: NegacyclicRing, private: PrivateKeypair, public: PublicKeypair) -> Vec<i32> {
-        let e2i = ring.ntt(ring.sample()).unwrap();
-        // let ki = ring.add(ring.mul(public.p, private.s).unwrap(), ring.mul(e2i, vec![2]).unwrap()).unwrap();
-        let ki = public.p.into_iter().zip(private.s).map(|(a, b)| a * b).zip(e2i).map(|(z, e)| (z + e * 2) % ring.modulus).collect();
+    fn parse_with_rng(ring: NegacyclicRing, noise: Noise, private: PrivateKeypair, public: ResponseKeypair, rng: &mut impl CryptoRng) -> Vec<i32> {
+        let e2i = noise.sample_ntt(ring, rng);
+        let ki = public.p * &private.s + e2i * 2;
 
-        let ski = ring.modulo2(ki, public.a);
-
-        ski
+        ring.modulo2(&ki, &public.w.to_ntt().unwrap()).into_coefficients()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
 
     #[test]
     fn bytes_transmute_public_keypair() {
         let ring = NegacyclicRing::new(8, 7681);
 
         for _ in 0..100 {
+            let mut seed = [0u8; SEED_SIZE];
+            Rand::new().fill_bytes(&mut seed);
+
             let keypair = PublicKeypair {
-                a: ring.sample(),
-                p: ring.sample()
+                seed,
+                p: NttPoly::new(ring, ring.sample()).unwrap()
             };
 
-            assert_eq!(PublicKeypair::from_bytes(keypair.clone().to_bytes()), keypair);
+            assert_eq!(PublicKeypair::from_bytes(&ring, keypair.clone().to_bytes(&ring)), Ok(keypair));
         }
     }
 
@@ -223,11 +408,66 @@ mod tests {
 
         for _ in 0..100 {
             let keypair = PrivateKeypair {
-                a: ring.sample(),
-                s: ring.sample()
+                s: NttPoly::new(ring, ring.sample()).unwrap()
+            };
+
+            assert_eq!(PrivateKeypair::from_bytes(&ring, keypair.clone().to_bytes(&ring)), Ok(keypair));
+        }
+    }
+
+    #[test]
+    fn bytes_transmute_response_keypair() {
+        let ring = NegacyclicRing::new(8, 7681);
+
+        for _ in 0..100 {
+            let keypair = ResponseKeypair {
+                w: Poly::new(ring, ring.sample_cbd(1).into_iter().map(|v| v % 2).collect()).unwrap(),
+                p: NttPoly::new(ring, ring.sample()).unwrap()
             };
 
-            assert_eq!(PrivateKeypair::from_bytes(keypair.clone().to_bytes()), keypair);
+            assert_eq!(ResponseKeypair::from_bytes(&ring, keypair.clone().to_bytes(&ring)), Ok(keypair));
+        }
+    }
+
+    #[test]
+    fn bytes_transmute_rejects_malformed() {
+        // 13 bits per coefficient, so 13 bytes for a polynomial of 8
+        let ring = NegacyclicRing::new(3, 7681);
+
+        assert_eq!(PublicKeypair::from_bytes(&ring, vec![0; 31]), Err(Error::Truncated));
+        assert_eq!(PublicKeypair::from_bytes(&ring, vec![0; 44]), Err(Error::Truncated));
+        assert_eq!(PublicKeypair::from_bytes(&ring, vec![0; 46]), Err(Error::TrailingData));
+        assert_eq!(PrivateKeypair::from_bytes(&ring, vec![0xff; 13]), Err(Error::CoefficientOutOfRange));
+        assert_eq!(ResponseKeypair::from_bytes(&ring, vec![]), Err(Error::Truncated));
+        assert_eq!(ResponseKeypair::from_bytes(&ring, vec![0; 13]), Err(Error::Truncated));
+
+        assert_eq!(PublicKeypair::from_bytes(&ring, vec![0; 45]).map(|key| key.p.into_values()), Ok(vec![0; 8]));
+        assert_eq!(ResponseKeypair::from_bytes(&ring, vec![0xff; 14]), Err(Error::CoefficientOutOfRange));
+    }
+
+    #[test]
+    fn packed_sizes() {
+        let ring = NegacyclicRing::new(9, 25601);
+
+        // 15 bits per coefficient, and 1 bit per coefficient of the signal
+        assert_eq!(PublicKeypair::size(&ring), 32 + 960);
+        assert_eq!(ResponseKeypair::size(&ring), 64 + 960);
+
+        let (private1, public1) = RLWE::generate(ring, Noise::Gaussian);
+        let (_, public2) = RLWE::respond(ring, Noise::Gaussian, public1.clone());
+
+        assert_eq!(public1.to_bytes(&ring).len(), PublicKeypair::size(&ring));
+        assert_eq!(public2.to_bytes(&ring).len(), ResponseKeypair::size(&ring));
+        assert_eq!(private1.to_bytes(&ring).len(), 960);
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        for bits in 1..=30 {
+            let bound = 1 << bits;
+            let poly: Vec<i32> = (0..13).map(|i| ((i as i64 * 0x2545f491) % bound as i64) as i32).collect();
+
+            assert_eq!(unpack_polynomial(&pack_polynomial(&poly, bits), bits, 13, bound), Ok(poly));
         }
     }
 
@@ -236,11 +476,111 @@ mod tests {
         for _ in 0..10 {
             let ring = NegacyclicRing::new(9, 25601);
 
-            let (private1, public1) = RLWE::generate(ring);
-            let (key2, public2) = RLWE::respond(ring, public1);
-            let key1 = RLWE::parse(ring, private1, public2);
+            for noise in [Noise::Gaussian, Noise::Binomial(2), Noise::Binomial(8)] {
+                let (private1, public1) = RLWE::generate(ring, noise);
+                let (key2, public2) = RLWE::respond(ring, noise, public1);
+                let key1 = RLWE::parse(ring, noise, private1, public2);
+
+                assert_eq!(key1, key2);
+            }
+        }
+    }
+
+    #[test]
+    fn key_exchange_large_modulus() {
+        // The largest prime below 2^31 that is 1 mod 1024, so every product overflows an i32
+        let ring = NegacyclicRing::new(9, 2147473409);
+
+        for _ in 0..10 {
+            for noise in [Noise::Gaussian, Noise::Binomial(8)] {
+                let (private1, public1) = RLWE::generate(ring, noise);
+                let (key2, public2) = RLWE::respond(ring, noise, public1);
+                let key1 = RLWE::parse(ring, noise, private1, public2);
+
+                assert_eq!(key1, key2);
+            }
+        }
+    }
+
+    #[test]
+    fn reductions_give_the_same_exchange() {
+        use crate::reduce::Reduction;
+
+        let run = |reduction: Reduction| {
+            let ring = NegacyclicRing::new(9, 25601).with_reduction(reduction);
+
+            let (private1, public1) = RLWE::generate_with_rng(ring, Noise::Gaussian, &mut Rand::from_seed([1; 32]));
+            let (key2, public2) = RLWE::respond_with_rng(ring, Noise::Gaussian, public1.clone(), &mut Rand::from_seed([2; 32]));
+            let key1 = RLWE::parse_with_rng(ring, Noise::Gaussian, private1.clone(), public2.clone(), &mut Rand::from_seed([3; 32]));
+
+            // Compared encoded, as the keypairs are bound to rings with different reductions
+            assert_eq!(key1, key2);
+            (private1.to_bytes(&ring), public1.to_bytes(&ring), public2.to_bytes(&ring), key1)
+        };
+
+        let naive = run(Reduction::Division);
+        assert_eq!(run(Reduction::Barrett), naive);
+        assert_eq!(run(Reduction::Montgomery), naive);
+    }
+
+    #[test]
+    fn seeded_key_exchange_is_reproducible() {
+        let ring = NegacyclicRing::new(9, 25601);
+
+        let run = |seed: u8| {
+            let (private1, public1) = RLWE::generate_with_rng(ring, Noise::Gaussian, &mut Rand::from_seed([seed; 32]));
+            let (key2, public2) = RLWE::respond_with_rng(ring, Noise::Gaussian, public1.clone(), &mut Rand::from_seed([seed + 1; 32]));
+            let key1 = RLWE::parse_with_rng(ring, Noise::Gaussian, private1.clone(), public2.clone(), &mut Rand::from_seed([seed + 2; 32]));
 
             assert_eq!(key1, key2);
+            (private1, public1, public2, key1)
+        };
+
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(4));
+    }
+
+    #[test]
+    fn key_exchange_with_injected_rng() {
+        let ring = NegacyclicRing::new(9, 25601);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+
+        let (private1, public1) = RLWE::generate_with_rng(ring, Noise::Gaussian, &mut rng);
+        let (key2, public2) = RLWE::respond_with_rng(ring, Noise::Gaussian, public1, &mut rng);
+        let key1 = RLWE::parse_with_rng(ring, Noise::Gaussian, private1, public2, &mut rng);
+
+        assert_eq!(key1, key2);
+    }
+
+    #[test]
+    fn binomial_noise_distribution() {
+        let ring = NegacyclicRing::new(10, 12289);
+        let mut rng = Rand::from_seed([5; 32]);
+
+        for eta in [1, 2, 4, 8, 16] {
+            let samples: Vec<f64> = (0..100).flat_map(|_| Noise::Binomial(eta).sample(ring, &mut rng)).map(|v| {
+                (if v > ring.modulus / 2 { v - ring.modulus } else { v }) as f64
+            }).collect();
+
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let variance = samples.iter().map(|x| x * x).sum::<f64>() / samples.len() as f64;
+
+            assert!(samples.iter().all(|x| x.abs() <= eta as f64));
+            assert!(mean.abs() < 0.05, "eta {}: mean {}", eta, mean);
+            assert!((variance / (eta as f64 / 2.0) - 1.0).abs() < 0.05, "eta {}: variance {}", eta, variance);
         }
     }
+
+    #[test]
+    fn public_polynomial_from_seed() {
+        let ring = NegacyclicRing::new(9, 25601);
+        let (_, public1) = RLWE::generate(ring, Noise::Gaussian);
+
+        // The request carries the seed and one polynomial
+        assert_eq!(public1.clone().to_bytes(&ring).len(), SEED_SIZE + 960);
+
+        // Both sides expand the same a from the seed
+        assert_eq!(ring.rand_from_seed(&public1.seed), ring.rand_from_seed(&public1.seed));
+        assert_ne!(ring.rand_from_seed(&public1.seed), ring.rand_from_seed(&[0; SEED_SIZE]));
+    }
 }
@@ -0,0 +1,21 @@
+use std::sync::atomic::{Ordering, compiler_fence};
+
+/// Overwrites the bytes with zeroes, in a way the compiler cannot remove.
+///
+/// A plain `fill(0)` before a value is dropped is a dead store, which the optimiser is free to elide,
+/// so each byte is written volatile and a fence keeps later code from being moved above the writes.
+///
+///```
+///# use partav2::secret::*;
+/// let mut key = [0x5a; 32];
+/// wipe(&mut key);
+/// assert_eq!(key, [0; 32]);
+///```
+pub fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // SAFETY: the pointer comes from a mutable reference, so it is valid, aligned and unaliased
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
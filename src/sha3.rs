@@ -0,0 +1,352 @@
+/// Round constants for the iota step of Keccak-f[1600]
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+    0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+    0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008
+];
+
+/// Rotation offsets for the rho step, in the order lanes are visited by the pi step
+const RHO: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14,
+    27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44
+];
+
+/// Lane indices visited by the pi step, starting from lane 1
+const PI: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1
+];
+
+/// The padding suffix for the SHA3 hash functions
+const SHA3_PAD: u8 = 0b110;
+
+/// The padding suffix for the SHAKE extendable output functions
+const SHAKE_PAD: u8 = 0b11111;
+
+/// The Keccak-f[1600] permutation, on a state of 25 lanes indexed by x + 5y.
+///
+/// ```
+///# use partav2::sha3::*;
+/// let mut state = [0u64; 25];
+/// keccak_f(&mut state);
+/// assert_eq!(state[0], 0xf1258f7940e1dde7);
+/// ```
+pub fn keccak_f(state: &mut [u64; 25]) {
+    for rc in ROUND_CONSTANTS {
+        // theta
+        let mut c = [0u64; 5];
+        for x in 0..5 {
+            c[x] = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5 * y] ^= d;
+            }
+        }
+
+        // rho and pi
+        let mut last = state[1];
+        for i in 0..24 {
+            let tmp = state[PI[i]];
+            state[PI[i]] = last.rotate_left(RHO[i]);
+            last = tmp;
+        }
+
+        // chi
+        for y in 0..5 {
+            let mut row = [0u64; 5];
+            row.copy_from_slice(&state[5 * y .. 5 * y + 5]);
+            for x in 0..5 {
+                state[x + 5 * y] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+
+        // iota
+        state[0] ^= rc;
+    }
+}
+
+/// An incremental keccak sponge, with a rate in bytes and a padding suffix.
+///
+/// Input is absorbed until the first squeeze, after which the sponge is padded
+/// and can only be squeezed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sponge {
+    state: [u64; 25],
+    rate: usize,
+    pad: u8,
+
+    /// The byte offset into the rate of the next absorbed or squeezed byte
+    offset: usize,
+    squeezing: bool
+}
+
+impl Sponge {
+    /// A new empty sponge.
+    ///
+    /// The rate must be a whole number of lanes smaller than the state.
+    pub fn new(rate: usize, pad: u8) -> Self {
+        assert!(rate > 0 && rate < 200 && rate.is_multiple_of(8), "Sponge::new: rate: Must be a whole number of lanes smaller than the state.");
+
+        Self {
+            state: [0; 25],
+            rate,
+            pad,
+
+            offset: 0,
+            squeezing: false
+        }
+    }
+
+    /// XORs a byte into the state at a byte offset.
+    fn xor_byte(&mut self, offset: usize, byte: u8) {
+        self.state[offset / 8] ^= (byte as u64) << (8 * (offset % 8));
+    }
+
+    /// Absorbs more input into the sponge.
+    ///
+    /// Panics if the sponge has already been squeezed.
+    pub fn absorb(&mut self, input: &[u8]) {
+        assert!(!self.squeezing, "Sponge::absorb: Cannot absorb after squeezing.");
+
+        for byte in input {
+            self.xor_byte(self.offset, *byte);
+            self.offset += 1;
+
+            if self.offset == self.rate {
+                keccak_f(&mut self.state);
+                self.offset = 0;
+            }
+        }
+    }
+
+    /// Squeezes output from the sponge, padding the input on the first call.
+    pub fn squeeze(&mut self, out: &mut [u8]) {
+        if !self.squeezing {
+            self.xor_byte(self.offset, self.pad);
+            self.xor_byte(self.rate - 1, 0b10000000);
+            keccak_f(&mut self.state);
+
+            self.offset = 0;
+            self.squeezing = true;
+        }
+
+        for byte in out {
+            if self.offset == self.rate {
+                keccak_f(&mut self.state);
+                self.offset = 0;
+            }
+
+            *byte = (self.state[self.offset / 8] >> (8 * (self.offset % 8))) as u8;
+            self.offset += 1;
+        }
+    }
+}
+
+/// An incremental SHA3 hash function.
+///
+/// ```
+///# use partav2::sha3::*;
+/// let mut hash = Sha3::new(256);
+/// hash.absorb(b"ab");
+/// hash.absorb(b"c");
+/// assert_eq!(hash.finalise(), digest(256, b"abc"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sha3 {
+    sponge: Sponge,
+    bits: usize
+}
+
+impl Sha3 {
+    /// A new SHA3 hash, with a digest of 224, 256, 384 or 512 bits.
+    pub fn new(bits: usize) -> Self {
+        assert!(matches!(bits, 224 | 256 | 384 | 512), "Sha3::new: bits: Must be one of 224, 256, 384 or 512.");
+
+        Self {
+            sponge: Sponge::new(200 - bits / 4, SHA3_PAD),
+            bits
+        }
+    }
+
+    /// Absorbs more input into the hash.
+    pub fn absorb(&mut self, input: &[u8]) {
+        self.sponge.absorb(input);
+    }
+
+    /// Returns the digest of everything absorbed.
+    pub fn finalise(mut self) -> Vec<u8> {
+        let mut out = vec![0; self.bits / 8];
+        self.sponge.squeeze(&mut out);
+
+        out
+    }
+}
+
+/// An incremental SHAKE extendable output function.
+///
+/// ```
+///# use partav2::sha3::*;
+/// let mut xof = Shake::new(128);
+/// xof.absorb(b"abc");
+///
+/// let mut out = [0; 64];
+/// xof.squeeze(&mut out[..10]);
+/// xof.squeeze(&mut out[10..]);
+/// assert_eq!(out.to_vec(), shake(128, b"abc", 64));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shake {
+    sponge: Sponge
+}
+
+impl Shake {
+    /// A new SHAKE function, with a security of 128 or 256 bits.
+    pub fn new(bits: usize) -> Self {
+        assert!(matches!(bits, 128 | 256), "Shake::new: bits: Must be one of 128 or 256.");
+
+        Self {
+            sponge: Sponge::new(200 - bits / 4, SHAKE_PAD)
+        }
+    }
+
+    /// Absorbs more input into the function.
+    ///
+    /// Panics if output has already been squeezed.
+    pub fn absorb(&mut self, input: &[u8]) {
+        self.sponge.absorb(input);
+    }
+
+    /// Squeezes the next bytes of output.
+    pub fn squeeze(&mut self, out: &mut [u8]) {
+        self.sponge.squeeze(out);
+    }
+}
+
+/// SHA3 hash function, with a digest of 224, 256, 384 or 512 bits.
+///
+/// ```
+///# use partav2::sha3::*;
+/// assert_eq!(
+///     digest(256, &[]),
+///     vec![
+///         0xa7, 0xff, 0xc6, 0xf8, 0xbf, 0x1e, 0xd7, 0x66, 0x51, 0xc1, 0x47, 0x56, 0xa0, 0x61, 0xd6, 0x62,
+///         0xf5, 0x80, 0xff, 0x4d, 0xe4, 0x3b, 0x49, 0xfa, 0x82, 0xd8, 0x0a, 0x4b, 0x80, 0xf8, 0x43, 0x4a
+///     ]
+/// );
+/// ```
+pub fn digest(bits: usize, input: &[u8]) -> Vec<u8> {
+    let mut hash = Sha3::new(bits);
+    hash.absorb(input);
+
+    hash.finalise()
+}
+
+/// SHAKE extendable output function, with a security of 128 or 256 bits, producing len bytes.
+///
+/// ```
+///# use partav2::sha3::*;
+/// assert_eq!(
+///     shake(128, &[], 8),
+///     vec![0x7f, 0x9c, 0x2b, 0xa4, 0xe8, 0x8f, 0x82, 0x7d]
+/// );
+/// ```
+pub fn shake(bits: usize, input: &[u8], len: usize) -> Vec<u8> {
+    let mut xof = Shake::new(bits);
+    xof.absorb(input);
+
+    let mut out = vec![0; len];
+    xof.squeeze(&mut out);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// The (digest bits, message, digest) test vectors of the archived C++ implementation
+    fn archived_vectors() -> Vec<(usize, Vec<u8>, Vec<u8>)> {
+        let source = include_str!("../archive/sha3test.cpp");
+
+        source.split("testVector(").skip(2).map(|entry| {
+            let fields: Vec<&str> = entry.split(',').map(|field| field.trim()).collect();
+
+            let bits = fields[0].parse().unwrap();
+            let quoted = |field: &str| hex(field.split('"').nth(1).unwrap());
+
+            // The empty message is stored as "00" with a length of zero, every other length
+            // (sometimes written as an expression) matches its message
+            let msg = if fields[1] == "0" { vec![] } else { quoted(fields[2]) };
+
+            (bits, msg, quoted(fields[3]))
+        }).collect()
+    }
+
+    #[test]
+    fn archived_known_answers() {
+        let vectors = archived_vectors();
+        assert_eq!(vectors.len(), 460);
+
+        for (bits, msg, expected) in vectors {
+            assert_eq!(digest(bits, &msg), expected, "SHA3-{} of {} bytes", bits, msg.len());
+        }
+    }
+
+    #[test]
+    fn shake_known_answers() {
+        assert_eq!(shake(128, b"", 32), hex("7f9c2ba4e88f827d616045507605853ed73b8093f6efbc88eb1a6eacfa66ef26"));
+        assert_eq!(shake(256, b"", 64), hex("46b9dd2b0ba88d13233b3feb743eeb243fcd52ea62b81b82b50c27646ed5762fd75dc4ddd8c0f200cb05019d67b592f6fc821c49479ab48640292eacb3b7c4be"));
+        assert_eq!(shake(128, b"abc", 32), hex("5881092dd818bf5cf8a3ddb793fbcba74097d5c526a6d35f97b83351940f2cc8"));
+        assert_eq!(shake(256, b"abc", 32), hex("483366601360a8771c6863080cc4114d8db44530f8f1e1ee4f94ea37e78b5739"));
+    }
+
+    #[test]
+    fn incremental_matches_oneshot() {
+        let msg: Vec<u8> = (0..1000).map(|i| (i * 31 + 7) as u8).collect();
+
+        for bits in [224, 256, 384, 512] {
+            for split in [0, 1, 71, 72, 135, 136, 137, 500] {
+                let mut hash = Sha3::new(bits);
+                hash.absorb(&msg[..split]);
+                hash.absorb(&msg[split..]);
+
+                assert_eq!(hash.finalise(), digest(bits, &msg));
+            }
+        }
+
+        for bits in [128, 256] {
+            let expected = shake(bits, &msg, 1000);
+
+            for chunk in [1, 3, 136, 168, 200] {
+                let mut xof = Shake::new(bits);
+                for part in msg.chunks(chunk) {
+                    xof.absorb(part);
+                }
+
+                let mut out = vec![0; 1000];
+                for part in out.chunks_mut(chunk) {
+                    xof.squeeze(part);
+                }
+
+                assert_eq!(out, expected);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn absorb_after_squeeze() {
+        let mut xof = Shake::new(256);
+        xof.squeeze(&mut [0; 1]);
+        xof.absorb(&[0]);
+    }
+}
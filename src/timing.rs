@@ -0,0 +1,208 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::rand::Rand;
+
+/// The |t| above which a difference in timing is reported as a leak, as in dudect
+pub const THRESHOLD: f64 = 4.5;
+
+/// The percentiles at which slow measurements are cropped, as long measurements are mostly noise
+/// from interrupts and the scheduler rather than the code under test
+const CROPS: [f64; 6] = [0.5, 0.75, 0.9, 0.95, 0.99, 1.0];
+
+/// The running mean and variance of a class of measurements (Welford's algorithm)
+#[derive(Debug, Clone, Copy, Default)]
+struct Moments {
+    count: f64,
+    mean: f64,
+    m2: f64
+}
+
+impl Moments {
+    fn push(&mut self, x: f64) {
+        self.count += 1.0;
+
+        let delta = x - self.mean;
+        self.mean += delta / self.count;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        self.m2 / (self.count - 1.0)
+    }
+}
+
+/// Welch's t statistic between two classes of measurements.
+///
+/// It is zero if either class has fewer than two measurements.
+pub fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+    let moments = |xs: &[f64]| xs.iter().fold(Moments::default(), |mut m, &x| {
+        m.push(x);
+        m
+    });
+    let (a, b) = (moments(a), moments(b));
+
+    if a.count < 2.0 || b.count < 2.0 {
+        return 0.0;
+    }
+
+    let error = (a.variance() / a.count + b.variance() / b.count).sqrt();
+    if error == 0.0 {
+        return 0.0;
+    }
+
+    (a.mean - b.mean) / error
+}
+
+/// The result of a timing test
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    /// The largest |t| over every crop of the measurements
+    pub t: f64,
+    pub measurements: usize
+}
+
+impl Report {
+    /// Whether the timing of the two classes differed by more than the threshold
+    pub fn leaks(&self) -> bool {
+        self.t > THRESHOLD
+    }
+}
+
+/// Tests whether the time taken by a function depends on its input, in the style of dudect.
+///
+/// Each measurement runs the function on an input of a randomly chosen class, usually one fixed
+/// input against random ones, built before the clock starts. The classes are compared with Welch's
+/// t-test, after cropping the slowest measurements at a few percentiles.
+///
+/// A leak is only reported with confidence when |t| is well above the threshold, and timing is noisy,
+/// so this is a tool for finding regressions rather than a proof of constant time.
+pub fn test<T>(measurements: usize, mut input: impl FnMut(bool, &mut Rand) -> T, mut run: impl FnMut(&T)) -> Report {
+    let mut rng = Rand::new();
+
+    let classes: Vec<bool> = (0..measurements).map(|_| rng.next_i32() & 1 == 1).collect();
+    let inputs: Vec<T> = classes.iter().map(|&class| input(class, &mut rng)).collect();
+
+    let times: Vec<f64> = inputs.iter().map(|input| {
+        let start = Instant::now();
+        run(black_box(input));
+
+        start.elapsed().as_nanos() as f64
+    }).collect();
+
+    let mut sorted = times.clone();
+    sorted.sort_by(f64::total_cmp);
+
+    let t = CROPS.iter().map(|crop| {
+        let limit = sorted[((sorted.len() - 1) as f64 * crop) as usize];
+        let class = |c: bool| -> Vec<f64> {
+            times.iter().zip(&classes).filter(|(time, class)| **class == c && **time <= limit).map(|(time, _)| *time).collect()
+        };
+
+        welch_t(&class(false), &class(true)).abs()
+    }).fold(0.0, f64::max);
+
+    Report {
+        t,
+        measurements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring::NegacyclicRing;
+    use crate::poly::Poly;
+    use crate::kem::mlkem;
+
+    #[test]
+    fn welch_t_matches_reference() {
+        // The t statistic of scipy.stats.ttest_ind(a, b, equal_var=False)
+        let a = [19.8, 20.4, 19.6, 17.8, 18.5, 18.9, 18.3, 18.9, 19.5, 22.0];
+        let b = [28.2, 26.6, 20.1, 23.3, 25.2, 22.1, 17.7, 27.6, 20.6, 13.7, 23.2, 17.5, 20.6, 18.0, 23.9, 21.6, 24.3, 20.4, 23.9, 13.3];
+
+        assert!((welch_t(&a, &b) - -2.2255120).abs() < 1e-6, "{}", welch_t(&a, &b));
+        assert_eq!(welch_t(&a, &[1.0]), 0.0);
+        assert_eq!(welch_t(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]), 0.0);
+    }
+
+    #[test]
+    fn detects_a_sleep() {
+        // A leak of a tenth of a millisecond, far above any scheduler noise, so the harness itself
+        // is checked on every run
+        let report = test(1000, |class, _| class, |&slow| {
+            if slow {
+                std::thread::sleep(std::time::Duration::from_micros(100));
+            }
+        });
+
+        assert!(report.leaks(), "{:?}", report);
+    }
+
+    // The timing tests below are noisy on a busy machine, so are run on request, and in CI, with
+    // `cargo test --release -- --ignored timing`
+
+    #[test]
+    #[ignore]
+    fn detects_a_leak() {
+        let report = test(5000, |class, _| class, |&slow| {
+            if slow {
+                for i in 0..5000 {
+                    black_box(i);
+                }
+            }
+        });
+
+        assert!(report.leaks(), "{:?}", report);
+    }
+
+    #[test]
+    #[ignore]
+    fn signal_is_constant_time() {
+        let ring = NegacyclicRing::new(9, 25601);
+
+        let report = test(100000, |class, rng| Poly::new(ring, if class { ring.rand_with_rng(rng) } else { vec![ring.modulus / 2; 512] }).unwrap(), |poly| {
+            black_box(ring.signal(poly));
+        });
+
+        assert!(!report.leaks(), "{:?}", report);
+    }
+
+    #[test]
+    #[ignore]
+    fn power_is_constant_time() {
+        let ring = NegacyclicRing::new(9, 25601);
+
+        let report = test(100000, |class, rng| if class { rng.next_i32() & i32::MAX } else { 1 }, |&y| {
+            black_box(ring.power(3, y));
+        });
+
+        assert!(!report.leaks(), "{:?}", report);
+    }
+
+    #[test]
+    #[ignore]
+    fn modulo2_is_constant_time() {
+        let ring = NegacyclicRing::new(9, 25601);
+
+        let poly = |class: bool, rng: &mut Rand| Poly::new(ring, if class { ring.rand_with_rng(rng) } else { vec![0; 512] }).unwrap().to_ntt().unwrap();
+
+        let report = test(100000, |class, rng| (poly(class, rng), poly(class, rng)), |(a, b)| {
+            black_box(ring.modulo2(a, b));
+        });
+
+        assert!(!report.leaks(), "{:?}", report);
+    }
+
+    #[test]
+    #[ignore]
+    fn compress_is_constant_time() {
+        let q = mlkem::RING.modulus;
+
+        let report = test(100000, |class, rng| if class { rng.next_i32().rem_euclid(q) } else { q / 2 }, |&x| {
+            black_box(mlkem::compress(x, 1));
+        });
+
+        assert!(!report.leaks(), "{:?}", report);
+    }
+}
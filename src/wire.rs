@@ -0,0 +1,192 @@
+use crate::error::Error;
+
+/// The bytes at the start of every handshake message
+pub const MAGIC: [u8; 4] = *b"PTV2";
+
+/// The version of the wire format
+pub const VERSION: u8 = 1;
+
+/// The size of the frame header (magic, version, message type, parameter set, payload length)
+pub const FRAME_HEADER_SIZE: usize = 12;
+
+/// The parameter set ID of a custom ring, whose header (modulus, then exponent) starts the payload
+pub const CUSTOM_PARAMETERS: u16 = 0;
+
+/// The kind of handshake message carried by a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    /// A ring learning with errors request
+    Request,
+    /// A ring learning with errors response
+    Response,
+    /// A ring learning with errors request followed by an X25519 public key
+    HybridRequest,
+    /// A ring learning with errors response followed by an X25519 public key
+    HybridResponse,
+    /// An ML-KEM encapsulation key
+    KemRequest,
+    /// An ML-KEM ciphertext
+    KemResponse
+}
+
+impl MessageType {
+    /// The byte identifying the message type on the wire
+    pub fn to_byte(self) -> u8 {
+        match self {
+            MessageType::Request => 1,
+            MessageType::Response => 2,
+            MessageType::HybridRequest => 3,
+            MessageType::HybridResponse => 4,
+            MessageType::KemRequest => 5,
+            MessageType::KemResponse => 6
+        }
+    }
+
+    /// The message type of a byte on the wire, if it is one
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(MessageType::Request),
+            2 => Some(MessageType::Response),
+            3 => Some(MessageType::HybridRequest),
+            4 => Some(MessageType::HybridResponse),
+            5 => Some(MessageType::KemRequest),
+            6 => Some(MessageType::KemResponse),
+            _ => None
+        }
+    }
+}
+
+/// A framed handshake message.
+///
+///```
+///# use partav2::wire::*;
+/// let frame = Frame {
+///     message_type: MessageType::Request,
+///     parameters: 1,
+///     payload: vec![1, 2, 3]
+/// };
+///
+/// let bytes = frame.encode();
+/// assert_eq!(bytes.len(), FRAME_HEADER_SIZE + 3);
+/// assert_eq!(Frame::decode(&bytes), Ok(frame));
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub message_type: MessageType,
+    /// The ID of the parameter set, or CUSTOM_PARAMETERS, or the ML-KEM level of a KEM message
+    pub parameters: u16,
+    pub payload: Vec<u8>
+}
+
+impl Frame {
+    /// Writes the frame header, then the payload.
+    ///
+    /// Panics if the payload is too long for the length field, 4 GiB or more.
+    pub fn encode(&self) -> Vec<u8> {
+        let len = u32::try_from(self.payload.len()).expect("Frame::encode: payload: Must be shorter than 4 GiB.");
+
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.push(self.message_type.to_byte());
+        out.extend(self.parameters.to_le_bytes());
+        out.extend(len.to_le_bytes());
+        out.extend(&self.payload);
+
+        out
+    }
+
+    /// Reads a frame, checking its header before the payload is looked at.
+    ///
+    /// Fails if the magic is wrong, the version or message type is unknown, or the payload
+    /// length disagrees with the length field.
+    ///
+    ///```
+    ///# use partav2::wire::*;
+    ///# use partav2::error::Error;
+    /// assert_eq!(Frame::decode(b"PTV2"), Err(Error::Truncated));
+    /// assert_eq!(Frame::decode(b"RSA!\x01\x01\x01\x00\x00\x00\x00\x00"), Err(Error::BadHeader));
+    /// assert_eq!(Frame::decode(b"PTV2\x02\x01\x01\x00\x00\x00\x00\x00"), Err(Error::UnsupportedVersion));
+    ///```
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < FRAME_HEADER_SIZE {
+            return Err(Error::Truncated);
+        }
+
+        if bytes[0..4] != MAGIC {
+            return Err(Error::BadHeader);
+        }
+        if bytes[4] != VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let message_type = MessageType::from_byte(bytes[5]).ok_or(Error::BadHeader)?;
+        let parameters = u16::from_le_bytes(bytes[6..8].try_into().unwrap());
+        let len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+
+        let payload = &bytes[FRAME_HEADER_SIZE..];
+        if payload.len() < len {
+            return Err(Error::Truncated);
+        }
+        if payload.len() > len {
+            return Err(Error::BadHeader);
+        }
+
+        Ok(Self {
+            message_type,
+            parameters,
+            payload: payload.to_vec()
+        })
+    }
+
+    /// Reads a frame, and checks it has the expected message type.
+    pub fn decode_expecting(bytes: &[u8], message_type: MessageType) -> Result<Self, Error> {
+        let frame = Self::decode(bytes)?;
+
+        if frame.message_type != message_type {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let bytes = Frame {
+            message_type: MessageType::Response,
+            parameters: 7,
+            payload: vec![9; 20]
+        }.encode();
+
+        assert_eq!(&bytes[..FRAME_HEADER_SIZE], b"PTV2\x01\x02\x07\x00\x14\x00\x00\x00");
+
+        for len in 0..bytes.len() {
+            assert_eq!(Frame::decode(&bytes[..len]), Err(Error::Truncated));
+        }
+
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(Frame::decode(&long), Err(Error::BadHeader));
+
+        let mut bad = bytes.clone();
+        bad[5] = 0;
+        assert_eq!(Frame::decode(&bad), Err(Error::BadHeader));
+
+        assert_eq!(Frame::decode_expecting(&bytes, MessageType::Request), Err(Error::UnexpectedMessage));
+        assert_eq!(Frame::decode_expecting(&bytes, MessageType::Response).map(|frame| frame.payload), Ok(vec![9; 20]));
+    }
+
+    #[test]
+    fn message_types() {
+        for message_type in [MessageType::Request, MessageType::Response, MessageType::HybridRequest, MessageType::HybridResponse, MessageType::KemRequest, MessageType::KemResponse] {
+            assert_eq!(MessageType::from_byte(message_type.to_byte()), Some(message_type));
+        }
+
+        assert_eq!(MessageType::from_byte(0), None);
+        assert_eq!(MessageType::from_byte(7), None);
+    }
+}